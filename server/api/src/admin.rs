@@ -0,0 +1,200 @@
+//! Moderation endpoints for abusive checkins, gated behind the same
+//! `ADMIN_TOKEN` bearer token `delete_checkin` already accepts. There's no
+//! user-facing "report" flow yet, so the "flagged" list is the full checkin
+//! table (including hidden rows) ordered newest-first, narrowed with
+//! `?outliers_only=true` to users whose reputation score has dropped below
+//! [`crate::reputation::OUTLIER_SCORE_THRESHOLD`].
+
+use crate::audit;
+use crate::auth::admin_auth;
+use crate::cache::Cache;
+use crate::db::Db;
+use crate::errors::ApiError;
+use crate::model::{BanRequest, Checkin, ListCheckinsQuery, NewBannedClient, Page};
+use crate::schema::banned_clients;
+use diesel::prelude::*;
+use warp::http::StatusCode;
+use warp::{Filter, Rejection, Reply};
+
+pub fn routes(db: Db, cache: Cache) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path("admin").and(
+        list_flagged(db.clone())
+            .or(hide_checkin(db.clone(), cache))
+            .or(create_ban(db.clone()))
+            .or(audit_log(db.clone()))
+            .or(admin_auth().and(crate::webhooks::routes(db))),
+    )
+}
+
+fn audit_log(db: Db) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path!("audit-log")
+        .and(warp::get())
+        .and(admin_auth())
+        .and(warp::query::<audit::AuditLogQuery>())
+        .and(with_db(db))
+        .and_then(audit::list)
+}
+
+fn list_flagged(db: Db) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path!("checkins")
+        .and(warp::get())
+        .and(admin_auth())
+        .and(warp::query::<ListCheckinsQuery>())
+        .and(with_db(db))
+        .and_then(list_flagged_checkins)
+}
+
+async fn list_flagged_checkins(query: ListCheckinsQuery, db: Db) -> Result<impl Reply, Rejection> {
+    let page = query.page.max(1);
+    let per_page = query.per_page.clamp(1, 100);
+
+    let (items, total) = db
+        .run(move |conn| {
+            use crate::schema::checkins::dsl::*;
+
+            let outliers = if query.outliers_only {
+                Some(crate::reputation::outlier_user_ids(conn)?)
+            } else {
+                None
+            };
+
+            macro_rules! apply_filters {
+                ($q:expr) => {{
+                    let q = $q;
+                    match &outliers {
+                        Some(outliers) => q.filter(user_id.eq_any(outliers.clone())),
+                        None => q,
+                    }
+                }};
+            }
+
+            let total: i64 = apply_filters!(checkins.into_boxed())
+                .count()
+                .get_result(conn)?;
+            let items: Vec<Checkin> = apply_filters!(checkins.into_boxed())
+                .order(created_at.desc())
+                .limit(per_page)
+                .offset((page - 1) * per_page)
+                .load(conn)?;
+            Ok((items, total))
+        })
+        .await
+        .map_err(ApiError::from)
+        .map_err(warp::reject::custom)?;
+
+    let next_page = if page * per_page < total {
+        Some(page + 1)
+    } else {
+        None
+    };
+
+    Ok(warp::reply::json(&Page {
+        items,
+        total,
+        next_page,
+    }))
+}
+
+fn hide_checkin(db: Db, cache: Cache) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path!("checkins" / i32 / "hide")
+        .and(warp::post())
+        .and(admin_auth())
+        .and(crate::request_id::filter())
+        .and(with_db(db))
+        .and(crate::cache::filter(cache))
+        .and_then(hide)
+}
+
+async fn hide(
+    checkin_id: i32,
+    request_id: String,
+    db: Db,
+    cache: Cache,
+) -> Result<impl Reply, Rejection> {
+    db.run(move |conn| {
+        conn.transaction(|| {
+            use crate::schema::checkins::dsl;
+            diesel::update(dsl::checkins.find(checkin_id))
+                .set(dsl::hidden.eq(true))
+                .execute(conn)?;
+            audit::record(
+                conn,
+                "admin",
+                "hide",
+                "POST /admin/checkins/:id/hide",
+                "checkin",
+                checkin_id,
+                &serde_json::json!({ "hidden": true }),
+                &request_id,
+            )
+        })
+    })
+    .await
+    .map_err(ApiError::from)
+    .map_err(warp::reject::custom)?;
+
+    cache.invalidate_all();
+    Ok(StatusCode::NO_CONTENT)
+}
+
+fn create_ban(db: Db) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path!("bans")
+        .and(warp::post())
+        .and(admin_auth())
+        .and(crate::request_id::filter())
+        .and(warp::body::content_length_limit(1024))
+        .and(warp::body::json())
+        .and(with_db(db))
+        .and_then(ban)
+}
+
+async fn ban(request_id: String, request: BanRequest, db: Db) -> Result<impl Reply, Rejection> {
+    let new_ban = NewBannedClient {
+        subject_type: request.subject_type,
+        subject_id: request.subject_id,
+        reason: request.reason,
+    };
+
+    db.run(move |conn| {
+        conn.transaction(|| {
+            diesel::insert_into(banned_clients::table)
+                .values(&new_ban)
+                .on_conflict_do_nothing()
+                .execute(conn)?;
+            audit::record(
+                conn,
+                "admin",
+                "create",
+                "POST /admin/bans",
+                "ban",
+                &new_ban.subject_id,
+                &new_ban,
+                &request_id,
+            )
+        })
+    })
+    .await
+    .map_err(ApiError::from)
+    .map_err(warp::reject::custom)?;
+
+    Ok(StatusCode::CREATED)
+}
+
+/// True if `client_id` or `user_id` has been banned by an admin.
+pub fn is_banned(conn: &PgConnection, client_id: &str, user_id: &str) -> QueryResult<bool> {
+    use crate::schema::banned_clients::dsl::*;
+    let count: i64 = banned_clients
+        .filter(
+            subject_type
+                .eq("client")
+                .and(subject_id.eq(client_id))
+                .or(subject_type.eq("user").and(subject_id.eq(user_id))),
+        )
+        .count()
+        .get_result(conn)?;
+    Ok(count > 0)
+}
+
+fn with_db(db: Db) -> impl Filter<Extract = (Db,), Error = std::convert::Infallible> + Clone {
+    warp::any().map(move || db.clone())
+}
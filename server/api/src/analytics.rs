@@ -0,0 +1,101 @@
+//! Aggregation endpoints for the map's heat layer, kept separate from
+//! `handlers.rs` since they're read-only SQL aggregations rather than CRUD.
+
+use crate::cache::Cache;
+use crate::db::Db;
+use crate::errors::ApiError;
+use diesel::dsl::sql_query;
+use diesel::sql_types::{Double, Text};
+use diesel::RunQueryDsl;
+use serde::{Deserialize, Serialize};
+use warp::{Rejection, Reply};
+
+/// Grid size in degrees for `ST_SnapToGrid`; roughly 100m at mid-latitudes.
+const GRID_SIZE_DEGREES: f64 = 0.001;
+
+#[derive(Debug, Deserialize)]
+pub struct HeatmapQuery {
+    /// `min_lng,min_lat,max_lng,max_lat`.
+    pub bbox: String,
+    #[serde(default = "default_bucket")]
+    pub bucket: String,
+}
+
+fn default_bucket() -> String {
+    "hour".to_string()
+}
+
+#[derive(Debug, QueryableByName, Serialize)]
+pub struct HeatmapCell {
+    #[sql_type = "Double"]
+    pub lng: f64,
+    #[sql_type = "Double"]
+    pub lat: f64,
+    #[sql_type = "diesel::sql_types::Timestamp"]
+    pub bucket_start: chrono::NaiveDateTime,
+    #[sql_type = "Double"]
+    pub average_crowded_level: f64,
+    #[sql_type = "diesel::sql_types::BigInt"]
+    pub count: i64,
+}
+
+pub async fn heatmap(query: HeatmapQuery, cache: Cache, db: Db) -> Result<impl Reply, Rejection> {
+    let bucket = match query.bucket.as_str() {
+        "day" => "day",
+        _ => "hour",
+    };
+    let bbox: Vec<f64> = query
+        .bbox
+        .split(',')
+        .filter_map(|s| s.trim().parse().ok())
+        .collect();
+    if bbox.len() != 4 {
+        return Err(warp::reject::custom(ApiError::Validation(
+            "bbox must be \"min_lng,min_lat,max_lng,max_lat\"".to_string(),
+        )));
+    }
+    let (min_lng, min_lat, max_lng, max_lat) = (bbox[0], bbox[1], bbox[2], bbox[3]);
+
+    let cache_key = format!("heatmap:{}:{}", query.bbox, bucket);
+    if let Some(cached) = cache.get(&cache_key) {
+        return Ok(warp::reply::with_header(
+            cached,
+            "Content-Type",
+            "application/json",
+        ));
+    }
+
+    let q = format!(
+        "select \
+            ST_X(ST_Centroid(ST_SnapToGrid(gps::geometry, {grid}))) as lng, \
+            ST_Y(ST_Centroid(ST_SnapToGrid(gps::geometry, {grid}))) as lat, \
+            date_trunc($1, observed_at) as bucket_start, \
+            avg(crowded_level)::float8 as average_crowded_level, \
+            count(*) as count \
+         from checkins \
+         where not hidden \
+           and gps && ST_MakeEnvelope({min_lng}, {min_lat}, {max_lng}, {max_lat}, 4326) \
+         group by lng, lat, bucket_start \
+         order by bucket_start asc",
+        grid = GRID_SIZE_DEGREES,
+        min_lng = min_lng,
+        min_lat = min_lat,
+        max_lng = max_lng,
+        max_lat = max_lat,
+    );
+
+    let cells: Vec<HeatmapCell> = db
+        .run(move |conn| sql_query(q).bind::<Text, _>(bucket).get_results(conn))
+        .await
+        .map_err(ApiError::from)
+        .map_err(warp::reject::custom)?;
+
+    let body = serde_json::to_string(&cells).expect("serialize heatmap cells");
+    cache.put(cache_key, body.clone());
+
+    Ok(warp::reply::with_header(
+        body,
+        "Content-Type",
+        "application/json",
+    ))
+}
@@ -0,0 +1,145 @@
+//! Append-only record of writes to moderation-relevant state (checkin
+//! create/update/delete, admin hides and bans), written to `audit_log` in the
+//! same transaction as the change it describes -- a rolled-back write can
+//! never leave behind an audit row that claims it happened. Exists so
+//! moderation disputes ("who changed this, and when") can be answered from
+//! the database instead of grepped out of logs, via the admin endpoint at
+//! the bottom of this file.
+
+use crate::db::Db;
+use crate::errors::ApiError;
+use crate::model::Page;
+use crate::schema::audit_log;
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use warp::{Rejection, Reply};
+
+#[derive(Debug, Insertable)]
+#[table_name = "audit_log"]
+struct NewAuditLogEntry {
+    actor: String,
+    action: String,
+    route: String,
+    entity_type: String,
+    entity_id: String,
+    diff: Value,
+    request_id: String,
+}
+
+/// Records one write. Always call this with the same `conn` the write itself
+/// ran on, inside the same transaction, so the audit row commits or rolls
+/// back together with the change it describes.
+pub fn record(
+    conn: &PgConnection,
+    actor: &str,
+    action: &str,
+    route: &str,
+    entity_type: &str,
+    entity_id: impl ToString,
+    diff: &impl Serialize,
+    request_id: &str,
+) -> QueryResult<()> {
+    diesel::insert_into(audit_log::table)
+        .values(NewAuditLogEntry {
+            actor: actor.to_string(),
+            action: action.to_string(),
+            route: route.to_string(),
+            entity_type: entity_type.to_string(),
+            entity_id: entity_id.to_string(),
+            diff: serde_json::to_value(diff).unwrap_or(Value::Null),
+            request_id: request_id.to_string(),
+        })
+        .execute(conn)?;
+    Ok(())
+}
+
+#[derive(Debug, Queryable, Serialize)]
+pub struct AuditLogEntry {
+    pub id: i32,
+    pub actor: String,
+    pub action: String,
+    pub route: String,
+    pub entity_type: String,
+    pub entity_id: String,
+    pub diff: Value,
+    pub request_id: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AuditLogQuery {
+    #[serde(default = "default_page")]
+    pub page: i64,
+    #[serde(default = "default_per_page")]
+    pub per_page: i64,
+    pub actor: Option<String>,
+    pub action: Option<String>,
+    pub entity_type: Option<String>,
+    pub entity_id: Option<String>,
+}
+
+fn default_page() -> i64 {
+    1
+}
+
+fn default_per_page() -> i64 {
+    50
+}
+
+/// `GET /admin/audit-log`: paginated, filterable view over every recorded
+/// write. Gated behind `admin_auth` the same as the rest of `admin.rs`.
+pub async fn list(query: AuditLogQuery, db: Db) -> Result<impl Reply, Rejection> {
+    let page = query.page.max(1);
+    let per_page = query.per_page.clamp(1, 200);
+
+    let (items, total) = db
+        .run(move |conn| {
+            use crate::schema::audit_log::dsl;
+
+            macro_rules! apply_filters {
+                ($q:expr) => {{
+                    let mut q = $q;
+                    if let Some(actor) = &query.actor {
+                        q = q.filter(dsl::actor.eq(actor.clone()));
+                    }
+                    if let Some(action) = &query.action {
+                        q = q.filter(dsl::action.eq(action.clone()));
+                    }
+                    if let Some(entity_type) = &query.entity_type {
+                        q = q.filter(dsl::entity_type.eq(entity_type.clone()));
+                    }
+                    if let Some(entity_id) = &query.entity_id {
+                        q = q.filter(dsl::entity_id.eq(entity_id.clone()));
+                    }
+                    q
+                }};
+            }
+
+            let total: i64 = apply_filters!(dsl::audit_log.into_boxed())
+                .count()
+                .get_result(conn)?;
+            let items: Vec<AuditLogEntry> = apply_filters!(dsl::audit_log.into_boxed())
+                .order(dsl::created_at.desc())
+                .limit(per_page)
+                .offset((page - 1) * per_page)
+                .load(conn)?;
+            Ok((items, total))
+        })
+        .await
+        .map_err(ApiError::from)
+        .map_err(warp::reject::custom)?;
+
+    let next_page = if page * per_page < total {
+        Some(page + 1)
+    } else {
+        None
+    };
+
+    Ok(warp::reply::json(&Page {
+        items,
+        total,
+        next_page,
+    }))
+}
@@ -0,0 +1,87 @@
+//! Bearer-token auth for write endpoints. Each mobile client is issued an API
+//! key stored in the `clients` table; `bearer_auth` resolves the header to the
+//! `client_id` it was issued for so handlers can check it against the body.
+
+use crate::db::Db;
+use crate::errors::ApiError;
+use crate::model::{Client, NewClient};
+use diesel::prelude::*;
+use uuid::Uuid;
+use warp::Filter;
+
+#[derive(Debug, Clone)]
+pub struct AuthenticatedClient {
+    pub client_id: String,
+}
+
+pub fn bearer_auth(
+    db: Db,
+) -> impl Filter<Extract = (AuthenticatedClient,), Error = warp::Rejection> + Clone {
+    warp::header::<String>("authorization")
+        .and(warp::any().map(move || db.clone()))
+        .and_then(authenticate)
+}
+
+/// True if `header` is `Bearer <ADMIN_TOKEN>` for the token configured via the
+/// `ADMIN_TOKEN` environment variable. Absent either side, admin access is denied.
+pub fn is_admin(header: &Option<String>) -> bool {
+    let configured = match std::env::var("ADMIN_TOKEN") {
+        Ok(token) if !token.is_empty() => token,
+        _ => return false,
+    };
+    match header {
+        Some(h) => h.strip_prefix("Bearer ").unwrap_or(h) == configured,
+        None => false,
+    }
+}
+
+/// Gate for admin-only endpoints: rejects with `ApiError::Forbidden` unless
+/// `Authorization` carries the `ADMIN_TOKEN` bearer token.
+pub fn admin_auth() -> impl Filter<Extract = (), Error = warp::Rejection> + Clone {
+    warp::header::optional::<String>("authorization")
+        .and_then(|header: Option<String>| async move {
+            if is_admin(&header) {
+                Ok(())
+            } else {
+                Err(warp::reject::custom(ApiError::Forbidden(
+                    "admin token required".to_string(),
+                )))
+            }
+        })
+        .untuple_one()
+}
+
+async fn authenticate(header: String, db: Db) -> Result<AuthenticatedClient, warp::Rejection> {
+    let presented_key = header
+        .strip_prefix("Bearer ")
+        .unwrap_or(&header)
+        .to_string();
+
+    let found: String = db
+        .run(move |conn| {
+            use crate::schema::clients::dsl::*;
+            clients
+                .filter(api_key.eq(presented_key))
+                .select(client_id)
+                .first(conn)
+        })
+        .await
+        .map_err(|_| warp::reject::custom(ApiError::Validation("invalid API key".to_string())))?;
+
+    Ok(AuthenticatedClient { client_id: found })
+}
+
+/// Issues a fresh API key for `client_id`, used by the `create-api-key` CLI
+/// subcommand. Generates the key itself rather than taking one as an
+/// argument, same as `request_id::filter` generates ids with `Uuid::new_v4`.
+pub fn create_api_key(conn: &PgConnection, client_id: &str) -> QueryResult<Client> {
+    use crate::schema::clients;
+
+    let new_client = NewClient {
+        client_id: client_id.to_string(),
+        api_key: Uuid::new_v4().to_string(),
+    };
+    diesel::insert_into(clients::table)
+        .values(&new_client)
+        .get_result(conn)
+}
@@ -0,0 +1,185 @@
+//! Pluggable storage for the parts of the API that don't touch geography
+//! data, so moderation logic can be exercised without a provisioned
+//! Postgres+PostGIS instance (see `docker-compose.yml`).
+//!
+//! This deliberately covers only `banned_clients` — the same slice
+//! [`crate::admin::is_banned`] already uses. Checkins, locations and watches
+//! all store a `GEOGRAPHY(POINT)` column via `diesel-geography`, which has no
+//! SQLite counterpart, so `ST_DWithin`/`ST_GeoHash`-style crowding queries
+//! can't be answered from an in-memory backend. Widening `Storage` to those
+//! would mean reimplementing the geo queries against a second backend, which
+//! is its own project; this is the slice that's actually backend-agnostic
+//! today.
+//!
+//! `Db` (the real Postgres pool) implements [`Storage`] directly. With
+//! `--features sqlite`, [`SqliteStorage`] offers the same trait over an
+//! in-memory `SqliteConnection`, which is what the tests in this module run
+//! against.
+
+use crate::db::{Db, DbError};
+use futures::future::BoxFuture;
+
+/// Moderation operations that don't depend on geography data, and so can be
+/// served by any backend.
+pub trait Storage: Send + Sync {
+    fn is_banned(&self, client_id: String, user_id: String) -> BoxFuture<'static, Result<bool, DbError>>;
+
+    fn ban(
+        &self,
+        subject_type: String,
+        subject_id: String,
+        reason: Option<String>,
+    ) -> BoxFuture<'static, Result<(), DbError>>;
+}
+
+impl Storage for Db {
+    fn is_banned(&self, client_id: String, user_id: String) -> BoxFuture<'static, Result<bool, DbError>> {
+        let db = self.clone();
+        Box::pin(async move {
+            db.run(move |conn| crate::admin::is_banned(conn, &client_id, &user_id))
+                .await
+        })
+    }
+
+    fn ban(
+        &self,
+        subject_type: String,
+        subject_id: String,
+        reason: Option<String>,
+    ) -> BoxFuture<'static, Result<(), DbError>> {
+        use crate::model::NewBannedClient;
+        use crate::schema::banned_clients;
+        use diesel::prelude::*;
+
+        let db = self.clone();
+        Box::pin(async move {
+            db.run(move |conn| {
+                diesel::insert_into(banned_clients::table)
+                    .values(&NewBannedClient {
+                        subject_type,
+                        subject_id,
+                        reason,
+                    })
+                    .on_conflict_do_nothing()
+                    .execute(conn)
+                    .map(|_| ())
+            })
+            .await
+        })
+    }
+}
+
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteStorage;
+
+#[cfg(feature = "sqlite")]
+mod sqlite {
+    use super::Storage;
+    use crate::db::DbError;
+    use diesel::prelude::*;
+    use diesel::sqlite::SqliteConnection;
+    use futures::future::BoxFuture;
+    use std::sync::{Arc, Mutex};
+
+    /// In-memory `banned_clients` table, for running moderation logic in
+    /// tests and local demos without Docker.
+    pub struct SqliteStorage {
+        conn: Arc<Mutex<SqliteConnection>>,
+    }
+
+    impl SqliteStorage {
+        pub fn new() -> Self {
+            let conn = SqliteConnection::establish(":memory:").expect("open in-memory sqlite db");
+            diesel::sql_query(
+                "CREATE TABLE banned_clients (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    subject_type TEXT NOT NULL,
+                    subject_id TEXT NOT NULL,
+                    reason TEXT,
+                    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+                )",
+            )
+            .execute(&conn)
+            .expect("create banned_clients table");
+
+            SqliteStorage {
+                conn: Arc::new(Mutex::new(conn)),
+            }
+        }
+    }
+
+    impl Storage for SqliteStorage {
+        fn is_banned(&self, client_id: String, user_id: String) -> BoxFuture<'static, Result<bool, DbError>> {
+            let conn = self.conn.clone();
+            Box::pin(async move {
+                let conn = conn.lock().expect("sqlite connection poisoned");
+                let count: i64 = diesel::sql_query(
+                    "select count(*) as count from banned_clients \
+                     where (subject_type = 'client' and subject_id = ?) \
+                     or (subject_type = 'user' and subject_id = ?)",
+                )
+                .bind::<diesel::sql_types::Text, _>(client_id)
+                .bind::<diesel::sql_types::Text, _>(user_id)
+                .get_result::<Count>(&*conn)
+                .map_err(DbError::Query)?
+                .count;
+                Ok(count > 0)
+            })
+        }
+
+        fn ban(
+            &self,
+            subject_type: String,
+            subject_id: String,
+            reason: Option<String>,
+        ) -> BoxFuture<'static, Result<(), DbError>> {
+            let conn = self.conn.clone();
+            Box::pin(async move {
+                let conn = conn.lock().expect("sqlite connection poisoned");
+                diesel::sql_query(
+                    "insert into banned_clients (subject_type, subject_id, reason) values (?, ?, ?)",
+                )
+                .bind::<diesel::sql_types::Text, _>(subject_type)
+                .bind::<diesel::sql_types::Text, _>(subject_id)
+                .bind::<diesel::sql_types::Nullable<diesel::sql_types::Text>, _>(reason)
+                .execute(&*conn)
+                .map(|_| ())
+                .map_err(DbError::Query)
+            })
+        }
+    }
+
+    #[derive(QueryableByName)]
+    struct Count {
+        #[sql_type = "diesel::sql_types::BigInt"]
+        count: i64,
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[tokio::test]
+        async fn bans_and_checks_without_postgres() {
+            let storage = SqliteStorage::new();
+            assert!(!storage
+                .is_banned("client-1".to_string(), "user-1".to_string())
+                .await
+                .unwrap());
+
+            storage
+                .ban("client".to_string(), "client-1".to_string(), None)
+                .await
+                .unwrap();
+
+            assert!(storage
+                .is_banned("client-1".to_string(), "user-1".to_string())
+                .await
+                .unwrap());
+            assert!(!storage
+                .is_banned("client-2".to_string(), "user-1".to_string())
+                .await
+                .unwrap());
+        }
+    }
+}
@@ -0,0 +1,155 @@
+//! Pluggable storage for checkin photo blobs (see [`crate::photos`]).
+//! Unlike the optional [`crate::geocoding`]/[`crate::push`] subsystems, photo
+//! storage is never disabled: [`FsBlobStore`] writing under
+//! `photo_storage_dir` is always available, so a deployment with no object
+//! storage configured still works. `S3_BUCKET` swaps in [`S3BlobStore`]
+//! for deployments that want durable, CDN-fronted storage instead.
+
+use futures::future::BoxFuture;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+#[derive(Debug)]
+pub enum BlobError {
+    Io(std::io::Error),
+    S3(String),
+}
+
+impl fmt::Display for BlobError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BlobError::Io(e) => write!(f, "blob storage I/O error: {}", e),
+            BlobError::S3(e) => write!(f, "S3 request failed: {}", e),
+        }
+    }
+}
+
+impl From<std::io::Error> for BlobError {
+    fn from(e: std::io::Error) -> Self {
+        BlobError::Io(e)
+    }
+}
+
+/// How a stored blob should be handed back to the client. Filesystem storage
+/// can only ever return bytes; an S3 backend would rather redirect a client
+/// straight to the bucket than proxy the body through this server.
+pub enum BlobDelivery {
+    Bytes(Vec<u8>),
+    Redirect(String),
+}
+
+/// A place to put and get checkin photo bytes, keyed by an opaque
+/// `storage_key` chosen by the caller (see `crate::photos::storage_key_for`).
+pub trait BlobStore: Send + Sync {
+    fn put(
+        &self,
+        key: &str,
+        content_type: &str,
+        bytes: Vec<u8>,
+    ) -> BoxFuture<'static, Result<(), BlobError>>;
+
+    fn fetch(&self, key: &str) -> BoxFuture<'static, Result<BlobDelivery, BlobError>>;
+}
+
+/// Shared blob store injected into filters the same way `Db`/`Cache` are.
+/// Always present — see the module doc comment for why this isn't `Option`
+/// like [`crate::geocoding::SharedGeocoder`].
+pub type SharedBlobStore = Arc<dyn BlobStore>;
+
+/// Stores each blob as a file under `root`, named by its storage key. The
+/// default backend: always available, no external service required.
+pub struct FsBlobStore {
+    root: PathBuf,
+}
+
+impl FsBlobStore {
+    pub fn new(root: PathBuf) -> Self {
+        FsBlobStore { root }
+    }
+
+    fn path_for(&self, key: &str) -> PathBuf {
+        self.root.join(key)
+    }
+}
+
+impl BlobStore for FsBlobStore {
+    fn put(
+        &self,
+        key: &str,
+        _content_type: &str,
+        bytes: Vec<u8>,
+    ) -> BoxFuture<'static, Result<(), BlobError>> {
+        let path = self.path_for(key);
+        Box::pin(async move {
+            if let Some(parent) = path.parent() {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+            tokio::fs::write(&path, bytes).await?;
+            Ok(())
+        })
+    }
+
+    fn fetch(&self, key: &str) -> BoxFuture<'static, Result<BlobDelivery, BlobError>> {
+        let path = self.path_for(key);
+        Box::pin(async move {
+            let bytes = tokio::fs::read(&path).await?;
+            Ok(BlobDelivery::Bytes(bytes))
+        })
+    }
+}
+
+/// Stores blobs in S3, used when `S3_BUCKET` is configured. `fetch` doesn't
+/// proxy the object through this server: it hands back the bucket's public
+/// object URL and lets [`crate::photos::get_photo`] redirect the client
+/// straight to it.
+pub struct S3BlobStore {
+    bucket: String,
+    client: rusoto_s3::S3Client,
+}
+
+impl S3BlobStore {
+    pub fn new(bucket: String, region: rusoto_core::Region) -> Self {
+        S3BlobStore {
+            bucket,
+            client: rusoto_s3::S3Client::new(region),
+        }
+    }
+
+    fn object_url(&self, key: &str) -> String {
+        format!("https://{}.s3.amazonaws.com/{}", self.bucket, key)
+    }
+}
+
+impl BlobStore for S3BlobStore {
+    fn put(
+        &self,
+        key: &str,
+        content_type: &str,
+        bytes: Vec<u8>,
+    ) -> BoxFuture<'static, Result<(), BlobError>> {
+        use rusoto_s3::{PutObjectRequest, S3};
+        let client = self.client.clone();
+        let bucket = self.bucket.clone();
+        let key = key.to_string();
+        let content_type = content_type.to_string();
+        Box::pin(async move {
+            client
+                .put_object(PutObjectRequest {
+                    bucket,
+                    key,
+                    body: Some(bytes.into()),
+                    content_type: Some(content_type),
+                    ..Default::default()
+                })
+                .await
+                .map_err(|e| BlobError::S3(e.to_string()))?;
+            Ok(())
+        })
+    }
+
+    fn fetch(&self, key: &str) -> BoxFuture<'static, Result<BlobDelivery, BlobError>> {
+        let url = self.object_url(key);
+        Box::pin(async move { Ok(BlobDelivery::Redirect(url)) })
+    }
+}
@@ -0,0 +1,62 @@
+//! Small TTL cache for expensive read-only aggregations (stats, heatmap), so
+//! a popular map doesn't turn every pan/zoom into a fresh Postgres query.
+//! Shared the same way `Db`/`ws::Registry` are: built once in `main.rs` and
+//! cloned into filters via `filter()`. Keys are the endpoint name plus its
+//! normalized query params, so different routes (and different params) never
+//! collide; a new checkin invalidates every entry rather than trying to
+//! reason about which keys it could have affected.
+
+use dashmap::DashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use warp::Filter;
+
+struct Entry {
+    body: String,
+    expires_at: Instant,
+}
+
+#[derive(Clone)]
+pub struct Cache {
+    entries: Arc<DashMap<String, Entry>>,
+    ttl: Duration,
+}
+
+impl Cache {
+    pub fn new(ttl_secs: u64) -> Self {
+        Cache {
+            entries: Arc::new(DashMap::new()),
+            ttl: Duration::from_secs(ttl_secs),
+        }
+    }
+
+    /// Returns the cached body for `key`, if present and not yet expired.
+    pub fn get(&self, key: &str) -> Option<String> {
+        let entry = self.entries.get(key)?;
+        if Instant::now() >= entry.expires_at {
+            return None;
+        }
+        Some(entry.body.clone())
+    }
+
+    /// Stores `body` for `key`, resetting its TTL.
+    pub fn put(&self, key: String, body: String) {
+        self.entries.insert(
+            key,
+            Entry {
+                body,
+                expires_at: Instant::now() + self.ttl,
+            },
+        );
+    }
+
+    /// Drops every cached entry; called whenever a checkin is inserted so
+    /// stale aggregates are never served.
+    pub fn invalidate_all(&self) {
+        self.entries.clear();
+    }
+}
+
+pub fn filter(cache: Cache) -> impl Filter<Extract = (Cache,), Error = std::convert::Infallible> + Clone {
+    warp::any().map(move || cache.clone())
+}
@@ -0,0 +1,36 @@
+//! Reusable weak-ETag support for read endpoints whose underlying data
+//! changes far less often than clients poll them (the frontend re-fetches
+//! `GET /v1/checkins` on a timer). The ETag is built from `(row count, most
+//! recent timestamp)` rather than hashing the response body, since every
+//! caller already has both cheaply from its own aggregate query.
+
+use chrono::NaiveDateTime;
+use warp::http::StatusCode;
+use warp::reply::Response;
+use warp::Reply;
+
+/// Builds a weak ETag from a row count and the newest timestamp in scope, so
+/// it changes whenever a row is added to or removed from the query's scope.
+/// An in-place edit that leaves both values unchanged (the callers pass the
+/// newest `created_at`) keeps the same tag.
+pub fn weak_etag(count: i64, max_timestamp: Option<NaiveDateTime>) -> String {
+    format!(
+        "W/\"{}-{}\"",
+        count,
+        max_timestamp.map(|t| t.timestamp()).unwrap_or(0)
+    )
+}
+
+/// Returns `304 Not Modified` if `if_none_match` already matches `etag`,
+/// otherwise tags `reply` with the `ETag` and a short `Cache-Control` max-age.
+pub fn with_etag(etag: &str, if_none_match: Option<String>, reply: impl Reply) -> Response {
+    if if_none_match.as_deref() == Some(etag) {
+        return warp::reply::with_status(warp::reply(), StatusCode::NOT_MODIFIED).into_response();
+    }
+    warp::reply::with_header(
+        warp::reply::with_header(reply, "ETag", etag),
+        "Cache-Control",
+        "max-age=10",
+    )
+    .into_response()
+}
@@ -0,0 +1,128 @@
+//! Command-line surface for the server binary's operator subcommands,
+//! parsed with `clap` so `--help` documents them instead of an operator
+//! having to read `main.rs`. `serve` (the default when no subcommand is
+//! given) starts the HTTP API; everything else is a one-off maintenance
+//! task that used to mean reaching for raw SQL against the database.
+
+use chrono::NaiveDateTime;
+use clap::{App, Arg, SubCommand};
+
+pub enum Command {
+    Serve,
+    Migrate,
+    Purge { older_than_days: i64 },
+    Export {
+        format: String,
+        from: NaiveDateTime,
+        to: NaiveDateTime,
+    },
+    CreateApiKey { client_id: String },
+    SeedDemo,
+    PseudonymizeMigrate,
+}
+
+const DATETIME_FORMAT: &str = "%Y-%m-%dT%H:%M:%S";
+
+pub fn parse() -> Command {
+    let matches = App::new("virus-vs-toilettenpapier")
+        .about("Crowding-report API server and operator maintenance tasks")
+        .subcommand(SubCommand::with_name("serve").about("Starts the HTTP API (default)"))
+        .subcommand(
+            SubCommand::with_name("migrate").about("Runs pending database migrations and exits"),
+        )
+        .subcommand(
+            SubCommand::with_name("purge")
+                .about("Deletes checkins older than a cutoff, same query the retention job runs")
+                .arg(
+                    Arg::with_name("older-than")
+                        .long("older-than")
+                        .takes_value(true)
+                        .required(true)
+                        .help("e.g. 30d -- whole days only"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("export")
+                .about("Writes anonymized checkins to stdout, same shape as GET /export")
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .takes_value(true)
+                        .default_value("ndjson")
+                        .possible_values(&["ndjson", "csv"]),
+                )
+                .arg(
+                    Arg::with_name("from")
+                        .long("from")
+                        .takes_value(true)
+                        .required(true)
+                        .help("inclusive, e.g. 2020-07-01T00:00:00"),
+                )
+                .arg(
+                    Arg::with_name("to")
+                        .long("to")
+                        .takes_value(true)
+                        .required(true)
+                        .help("exclusive, e.g. 2020-08-01T00:00:00"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("create-api-key")
+                .about("Issues a new API key for a client_id")
+                .arg(
+                    Arg::with_name("client-id")
+                        .long("client-id")
+                        .takes_value(true)
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("seed-demo").about("Inserts demo checkins for local development"),
+        )
+        .subcommand(SubCommand::with_name("pseudonymize-migrate").about(
+            "Backfills pseudonymized user_id for rows written before PSEUDONYMIZATION_SECRET was set",
+        ))
+        .get_matches();
+
+    match matches.subcommand() {
+        ("migrate", _) => Command::Migrate,
+        ("purge", Some(sub)) => {
+            let raw = sub.value_of("older-than").expect("required arg");
+            Command::Purge {
+                older_than_days: parse_days(raw)
+                    .unwrap_or_else(|e| panic!("--older-than {}: {}", raw, e)),
+            }
+        }
+        ("export", Some(sub)) => Command::Export {
+            format: sub.value_of("format").unwrap_or("ndjson").to_string(),
+            from: parse_datetime(sub.value_of("from").expect("required arg")),
+            to: parse_datetime(sub.value_of("to").expect("required arg")),
+        },
+        ("create-api-key", Some(sub)) => Command::CreateApiKey {
+            client_id: sub.value_of("client-id").expect("required arg").to_string(),
+        },
+        ("seed-demo", _) => Command::SeedDemo,
+        ("pseudonymize-migrate", _) => Command::PseudonymizeMigrate,
+        _ => Command::Serve,
+    }
+}
+
+/// Parses the `<N>d` shorthand `--older-than` takes (e.g. `30d`) into a
+/// number of days -- whole days only, no other units.
+fn parse_days(raw: &str) -> Result<i64, String> {
+    let digits = raw
+        .strip_suffix('d')
+        .ok_or_else(|| "expected a value like \"30d\"".to_string())?;
+    digits
+        .parse::<i64>()
+        .map_err(|_| "expected a value like \"30d\"".to_string())
+}
+
+fn parse_datetime(raw: &str) -> NaiveDateTime {
+    NaiveDateTime::parse_from_str(raw, DATETIME_FORMAT).unwrap_or_else(|e| {
+        panic!(
+            "invalid datetime \"{}\" (expected {}): {}",
+            raw, DATETIME_FORMAT, e
+        )
+    })
+}
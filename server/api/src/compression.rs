@@ -0,0 +1,142 @@
+//! Gzip-encodes large response bodies when the client advertises support via
+//! `Accept-Encoding`. Warp 0.2 predates `warp::compression` (added in 0.3),
+//! so this wraps the whole route tree once in `main.rs`, the same way
+//! `request_id::with_header` does.
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::io::Write;
+use warp::http::HeaderValue;
+use warp::{Filter, Rejection, Reply};
+
+/// Bodies smaller than this aren't worth spending CPU on: gzip's own framing
+/// overhead eats most of the savings.
+const MIN_COMPRESSIBLE_BYTES: usize = 1024;
+
+/// Wraps `routes` so any response over [`MIN_COMPRESSIBLE_BYTES`] is
+/// gzip-encoded when the client's `Accept-Encoding` allows it. Set `enabled`
+/// to `false` when running behind a reverse proxy that already compresses,
+/// so the body isn't encoded twice.
+pub fn with_gzip<F, T>(
+    routes: F,
+    enabled: bool,
+) -> impl Filter<Extract = (warp::reply::Response,), Error = Rejection> + Clone
+where
+    F: Filter<Extract = (T,), Error = Rejection> + Clone,
+    T: Reply,
+{
+    warp::header::optional::<String>("accept-encoding")
+        .and(routes)
+        .and_then(move |accept_encoding: Option<String>, reply: T| async move {
+            let response = reply.into_response();
+            let response = if enabled && accepts_gzip(&accept_encoding) {
+                gzip_if_worthwhile(response).await
+            } else {
+                response
+            };
+            Ok::<_, Rejection>(response)
+        })
+}
+
+fn is_chunked(response: &warp::reply::Response) -> bool {
+    response
+        .headers()
+        .get("transfer-encoding")
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.split(',').any(|encoding| encoding.trim().eq_ignore_ascii_case("chunked")))
+        .unwrap_or(false)
+}
+
+fn accepts_gzip(accept_encoding: &Option<String>) -> bool {
+    accept_encoding
+        .as_deref()
+        .map(|header| {
+            header
+                .split(',')
+                .any(|encoding| encoding.trim().starts_with("gzip"))
+        })
+        .unwrap_or(false)
+}
+
+async fn gzip_if_worthwhile(response: warp::reply::Response) -> warp::reply::Response {
+    // Already encoded (e.g. a proxied or pre-compressed body) — leave it alone.
+    if response.headers().contains_key("content-encoding") {
+        return response;
+    }
+
+    // Chunked responses (the keyset-paginated streams in `streaming.rs`/
+    // `export.rs`) are chunked specifically so a large export never sits in
+    // memory as one `Vec<u8>` — draining them here to gzip would defeat that
+    // entirely. Leave the stream alone and let it go out uncompressed.
+    if is_chunked(&response) {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let bytes = match hyper::body::to_bytes(body).await {
+        Ok(bytes) => bytes,
+        Err(_) => return warp::reply::Response::from_parts(parts, hyper::Body::empty()),
+    };
+
+    if bytes.len() < MIN_COMPRESSIBLE_BYTES {
+        return warp::reply::Response::from_parts(parts, hyper::Body::from(bytes));
+    }
+
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    let compressed = match encoder.write_all(&bytes).and_then(|_| encoder.finish()) {
+        Ok(compressed) => compressed,
+        Err(_) => return warp::reply::Response::from_parts(parts, hyper::Body::from(bytes)),
+    };
+
+    parts
+        .headers
+        .insert("content-encoding", HeaderValue::from_static("gzip"));
+    parts.headers.insert(
+        "content-length",
+        HeaderValue::from_str(&compressed.len().to_string()).expect("digits are valid header value"),
+    );
+    warp::reply::Response::from_parts(parts, hyper::Body::from(compressed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_gzip_matches_common_header_shapes() {
+        assert!(accepts_gzip(&Some("gzip".to_string())));
+        assert!(accepts_gzip(&Some("deflate, gzip;q=0.8".to_string())));
+        assert!(!accepts_gzip(&Some("deflate, br".to_string())));
+        assert!(!accepts_gzip(&None));
+    }
+
+    #[tokio::test]
+    async fn gzip_if_worthwhile_skips_small_bodies() {
+        let response = warp::reply::Response::new(hyper::Body::from("short"));
+        let compressed = gzip_if_worthwhile(response).await;
+        assert!(!compressed.headers().contains_key("content-encoding"));
+    }
+
+    #[tokio::test]
+    async fn gzip_if_worthwhile_skips_chunked_streams() {
+        let body = "x".repeat(MIN_COMPRESSIBLE_BYTES + 1);
+        let mut response = warp::reply::Response::new(hyper::Body::from(body));
+        response
+            .headers_mut()
+            .insert("transfer-encoding", HeaderValue::from_static("chunked"));
+        let result = gzip_if_worthwhile(response).await;
+        assert!(!result.headers().contains_key("content-encoding"));
+        assert_eq!(result.headers().get("transfer-encoding").unwrap(), "chunked");
+    }
+
+    #[tokio::test]
+    async fn gzip_if_worthwhile_encodes_large_bodies() {
+        let body = "x".repeat(MIN_COMPRESSIBLE_BYTES + 1);
+        let response = warp::reply::Response::new(hyper::Body::from(body));
+        let compressed = gzip_if_worthwhile(response).await;
+        assert_eq!(
+            compressed.headers().get("content-encoding").unwrap(),
+            "gzip"
+        );
+    }
+}
@@ -0,0 +1,105 @@
+//! Runtime configuration read from the environment, with the defaults the
+//! server used to hardcode.
+
+use std::env;
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::PathBuf;
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub bind_addr: IpAddr,
+    pub port: u16,
+    pub database_pool_size: u32,
+    /// Minimum idle connections r2d2 keeps warm in the pool. Unset lets r2d2
+    /// default to `database_pool_size` (i.e. it tries to keep every slot
+    /// filled).
+    pub database_min_idle: Option<u32>,
+    /// How long `Pool::get` waits for a connection before giving up.
+    pub database_connection_timeout_secs: u64,
+    /// How many times to retry building the pool at startup if Postgres
+    /// isn't reachable yet, before giving up. Exists so the server doesn't
+    /// crash-loop ahead of its database under container orchestration, which
+    /// starts containers without guaranteeing Postgres is ready first.
+    pub database_connect_retries: u32,
+    /// Delay between startup connection retries.
+    pub database_connect_retry_delay_secs: u64,
+    pub body_limit_bytes: u64,
+    pub allowed_origins: Vec<String>,
+    pub checkin_rate_limit_per_minute: u32,
+    pub shutdown_timeout_secs: u64,
+    /// Both must be set to serve TLS directly; otherwise the server listens
+    /// in plaintext (the expected setup behind a TLS-terminating proxy).
+    pub tls_cert_path: Option<PathBuf>,
+    pub tls_key_path: Option<PathBuf>,
+    /// How long to keep checkins before the retention job purges them.
+    /// Unset disables the job entirely.
+    pub retention_days: Option<i64>,
+    /// How long a cached stats/heatmap aggregation is served before being
+    /// recomputed.
+    pub aggregation_cache_ttl_secs: u64,
+    /// Base URL of a Nominatim-compatible reverse-geocoding service. Unset
+    /// disables the geocoding subsystem entirely.
+    pub nominatim_base_url: Option<String>,
+    /// FCM legacy HTTP API server key used to deliver watch alerts. Unset
+    /// disables the push-notification subsystem entirely.
+    pub fcm_server_key: Option<String>,
+    /// Whether to gzip large responses ourselves. Turn off when running
+    /// behind a reverse proxy that already compresses, so the body isn't
+    /// encoded twice.
+    pub gzip_enabled: bool,
+    /// Where `FsBlobStore` writes checkin photos, used unless `s3_bucket` is
+    /// set.
+    pub photo_storage_dir: PathBuf,
+    /// S3 bucket to store checkin photos in instead of the local filesystem.
+    /// Unset keeps the default `FsBlobStore` backend.
+    pub s3_bucket: Option<String>,
+    /// Region for `s3_bucket`, ignored when `s3_bucket` is unset.
+    pub s3_region: String,
+    /// Largest photo upload accepted by `POST /v1/checkins/:id/photo`.
+    pub photo_limit_bytes: u64,
+    /// Base secret HMAC-pseudonymization of `user_id` rotates monthly under
+    /// (see [`crate::pseudonymize`]). Unset disables the subsystem entirely.
+    pub pseudonymization_secret: Option<String>,
+}
+
+impl Config {
+    pub fn from_env() -> Self {
+        Config {
+            bind_addr: env_parsed("BIND_ADDR").unwrap_or(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1))),
+            port: env_parsed("PORT").unwrap_or(3000),
+            database_pool_size: env_parsed("DATABASE_POOL_SIZE").unwrap_or(15),
+            database_min_idle: env_parsed("DATABASE_MIN_IDLE"),
+            database_connection_timeout_secs: env_parsed("DATABASE_CONNECTION_TIMEOUT_SECS")
+                .unwrap_or(30),
+            database_connect_retries: env_parsed("DATABASE_CONNECT_RETRIES").unwrap_or(5),
+            database_connect_retry_delay_secs: env_parsed("DATABASE_CONNECT_RETRY_DELAY_SECS")
+                .unwrap_or(2),
+            body_limit_bytes: env_parsed("BODY_LIMIT_BYTES").unwrap_or(1024 * 16),
+            allowed_origins: env::var("ALLOWED_ORIGINS")
+                .ok()
+                .map(|origins| origins.split(',').map(|o| o.trim().to_string()).collect())
+                .unwrap_or_else(|| vec!["http://localhost:5000".to_string()]),
+            checkin_rate_limit_per_minute: env_parsed("CHECKIN_RATE_LIMIT_PER_MINUTE")
+                .unwrap_or(30),
+            shutdown_timeout_secs: env_parsed("SHUTDOWN_TIMEOUT_SECS").unwrap_or(30),
+            tls_cert_path: env::var("TLS_CERT_PATH").ok().map(PathBuf::from),
+            tls_key_path: env::var("TLS_KEY_PATH").ok().map(PathBuf::from),
+            retention_days: env_parsed("RETENTION_DAYS"),
+            aggregation_cache_ttl_secs: env_parsed("AGGREGATION_CACHE_TTL_SECS").unwrap_or(30),
+            nominatim_base_url: env::var("NOMINATIM_BASE_URL").ok(),
+            fcm_server_key: env::var("FCM_SERVER_KEY").ok(),
+            gzip_enabled: env_parsed("GZIP_ENABLED").unwrap_or(true),
+            photo_storage_dir: env::var("PHOTO_STORAGE_DIR")
+                .map(PathBuf::from)
+                .unwrap_or_else(|_| PathBuf::from("photos")),
+            s3_bucket: env::var("S3_BUCKET").ok(),
+            s3_region: env::var("S3_REGION").unwrap_or_else(|_| "us-east-1".to_string()),
+            photo_limit_bytes: env_parsed("PHOTO_LIMIT_BYTES").unwrap_or(1024 * 1024 * 8),
+            pseudonymization_secret: env::var("PSEUDONYMIZATION_SECRET").ok(),
+        }
+    }
+}
+
+fn env_parsed<T: std::str::FromStr>(key: &str) -> Option<T> {
+    env::var(key).ok().and_then(|v| v.parse().ok())
+}
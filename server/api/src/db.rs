@@ -0,0 +1,97 @@
+//! Thin wrapper around the connection pool so handlers never block the tokio
+//! executor: every blocking Diesel call goes through `Db::run` (or
+//! `Db::with_tx` for multi-statement writes), which hands it off to
+//! `spawn_blocking` and reports pool/query failures uniformly.
+
+use crate::Pool;
+use diesel::connection::Connection;
+use diesel::r2d2::{ConnectionManager, PooledConnection, State};
+use diesel::PgConnection;
+use std::fmt;
+
+pub type Conn = PooledConnection<ConnectionManager<PgConnection>>;
+
+#[derive(Debug)]
+pub enum DbError {
+    Pool(diesel::r2d2::Error),
+    Query(diesel::result::Error),
+}
+
+impl fmt::Display for DbError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DbError::Pool(e) => write!(f, "connection pool error: {}", e),
+            DbError::Query(e) => write!(f, "query error: {}", e),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Db {
+    pool: Pool,
+}
+
+impl Db {
+    pub fn new(pool: Pool) -> Self {
+        Db { pool }
+    }
+
+    /// Runs `f` against a pooled connection on the blocking thread pool.
+    pub async fn run<F, T>(&self, f: F) -> Result<T, DbError>
+    where
+        F: FnOnce(&Conn) -> Result<T, diesel::result::Error> + Send + 'static,
+        T: Send + 'static,
+    {
+        let pool = self.pool.clone();
+        tokio::task::spawn_blocking(move || {
+            let conn = pool.get().map_err(DbError::Pool)?;
+            f(&conn).map_err(DbError::Query)
+        })
+        .await
+        .expect("db task panicked")
+    }
+
+    /// Like [`Db::run`], but wraps `f` in a transaction and retries it if
+    /// Postgres aborts the transaction for a serialization failure --
+    /// expected under concurrent writes to the same location/checkin rows,
+    /// and safe to retry since the whole transaction rolled back.
+    pub async fn with_tx<F, T>(&self, mut f: F) -> Result<T, DbError>
+    where
+        F: FnMut(&Conn) -> Result<T, diesel::result::Error> + Send + 'static,
+        T: Send + 'static,
+    {
+        let pool = self.pool.clone();
+        tokio::task::spawn_blocking(move || {
+            let conn = pool.get().map_err(DbError::Pool)?;
+            for attempt in 0..=MAX_TX_RETRIES {
+                match conn.transaction(|| f(&conn)) {
+                    Ok(value) => return Ok(value),
+                    Err(e) if attempt < MAX_TX_RETRIES && is_serialization_failure(&e) => {
+                        continue;
+                    }
+                    Err(e) => return Err(DbError::Query(e)),
+                }
+            }
+            unreachable!("loop always returns on its last iteration")
+        })
+        .await
+        .expect("db task panicked")
+    }
+
+    /// Snapshot of the r2d2 pool's connection counts, for the metrics gauges.
+    pub fn pool_state(&self) -> State {
+        self.pool.state()
+    }
+}
+
+const MAX_TX_RETRIES: u32 = 3;
+
+fn is_serialization_failure(err: &diesel::result::Error) -> bool {
+    matches!(
+        err,
+        diesel::result::Error::DatabaseError(
+            diesel::result::DatabaseErrorKind::SerializationFailure,
+            _
+        )
+    )
+}
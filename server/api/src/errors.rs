@@ -0,0 +1,106 @@
+//! Central place for turning failures into HTTP responses. Handlers reject with
+//! an `ApiError` via `warp::reject::custom`; `recover` turns any rejection
+//! (ours, warp's built-ins, or an unmatched route) into a JSON body with a
+//! stable `code` the client can match on.
+
+use crate::db::DbError;
+use serde::Serialize;
+use std::convert::Infallible;
+use warp::http::StatusCode;
+use warp::{Rejection, Reply};
+
+#[derive(Debug)]
+pub enum ApiError {
+    NotFound,
+    Validation(String),
+    DbPool(String),
+    DbQuery(String),
+    RateLimited(u64),
+    Conflict(String),
+    Forbidden(String),
+}
+
+impl warp::reject::Reject for ApiError {}
+
+/// How long a client should back off after a pool exhaustion/connection
+/// error before retrying, surfaced via `Retry-After` alongside the 503 --
+/// these are typically transient (a brief spike, or the db reconnecting).
+const DB_POOL_RETRY_AFTER_SECS: u64 = 5;
+
+impl From<DbError> for ApiError {
+    fn from(e: DbError) -> Self {
+        match e {
+            DbError::Pool(e) => ApiError::DbPool(e.to_string()),
+            DbError::Query(diesel::result::Error::NotFound) => ApiError::NotFound,
+            DbError::Query(e) => ApiError::DbQuery(e.to_string()),
+        }
+    }
+}
+
+fn status_code_and_message(err: &ApiError) -> (StatusCode, &'static str, String) {
+    match err {
+        ApiError::NotFound => (StatusCode::NOT_FOUND, "NOT_FOUND", "not found".to_string()),
+        ApiError::Validation(msg) => (StatusCode::UNPROCESSABLE_ENTITY, "VALIDATION", msg.clone()),
+        ApiError::DbPool(msg) => (
+            StatusCode::SERVICE_UNAVAILABLE,
+            "DB_POOL",
+            format!("database unavailable: {}", msg),
+        ),
+        ApiError::DbQuery(msg) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "DB_QUERY",
+            format!("database error: {}", msg),
+        ),
+        ApiError::RateLimited(retry_after) => (
+            StatusCode::TOO_MANY_REQUESTS,
+            "RATE_LIMITED",
+            format!("too many requests, retry after {}s", retry_after),
+        ),
+        ApiError::Conflict(msg) => (StatusCode::CONFLICT, "CONFLICT", msg.clone()),
+        ApiError::Forbidden(msg) => (StatusCode::FORBIDDEN, "FORBIDDEN", msg.clone()),
+    }
+}
+
+#[derive(Serialize)]
+struct ErrorBody {
+    code: &'static str,
+    message: String,
+}
+
+pub async fn recover(err: Rejection) -> Result<impl Reply, Infallible> {
+    let retry_after = match err.find::<ApiError>() {
+        Some(ApiError::RateLimited(seconds)) => Some(*seconds),
+        Some(ApiError::DbPool(_)) => Some(DB_POOL_RETRY_AFTER_SECS),
+        _ => None,
+    };
+
+    let (status, code, message) = if err.is_not_found() {
+        (StatusCode::NOT_FOUND, "NOT_FOUND", "route not found".to_string())
+    } else if let Some(api_err) = err.find::<ApiError>() {
+        status_code_and_message(api_err)
+    } else if let Some(body_err) = err.find::<warp::filters::body::BodyDeserializeError>() {
+        // Includes serde's own message, e.g. "unknown field `foo`, expected
+        // one of `gps`, `location_name`, ..." for a `deny_unknown_fields`
+        // rejection -- more useful to the client than a generic 400.
+        (StatusCode::BAD_REQUEST, "BAD_REQUEST", body_err.to_string())
+    } else {
+        error!("unhandled rejection: {:?}", err);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "INTERNAL",
+            "internal server error".to_string(),
+        )
+    };
+
+    let mut response =
+        warp::reply::with_status(warp::reply::json(&ErrorBody { code, message }), status)
+            .into_response();
+    if let Some(seconds) = retry_after {
+        response.headers_mut().insert(
+            "Retry-After",
+            warp::http::HeaderValue::from_str(&seconds.to_string()).unwrap(),
+        );
+    }
+
+    Ok(response)
+}
@@ -0,0 +1,186 @@
+//! Bulk anonymized export for epidemiology researchers. Streams rows as they
+//! come off a keyset-paginated query so a city's entire history can be
+//! downloaded without buffering it in memory; `user_id`/`client_id` are
+//! stripped and GPS coordinates are rounded to ~100m so individual reporters
+//! can't be re-identified from their movements.
+
+use crate::db::Db;
+use crate::errors::ApiError;
+use crate::model::Checkin;
+use crate::streaming::{self, PageResult};
+use bytes::Bytes;
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use diesel::PgConnection;
+use futures::future::BoxFuture;
+use futures::stream::{self, StreamExt};
+use hyper::Body;
+use serde::Deserialize;
+use std::io::{self, Write};
+use warp::http::Response;
+use warp::{Rejection, Reply};
+
+/// Same keyset page size the `GET /export` streaming response uses.
+const EXPORT_PAGE_SIZE: i64 = 500;
+
+/// Coordinates are rounded to the nearest 1/1000th of a degree (roughly
+/// 100m), matching the heatmap's grid size.
+const GPS_ROUNDING: f64 = 1000.0;
+
+#[derive(Debug, Deserialize)]
+pub struct ExportQuery {
+    pub from: chrono::NaiveDateTime,
+    pub to: chrono::NaiveDateTime,
+    #[serde(default = "default_format")]
+    pub format: String,
+}
+
+fn default_format() -> String {
+    "ndjson".to_string()
+}
+
+fn round_coord(v: f64) -> f64 {
+    (v * GPS_ROUNDING).round() / GPS_ROUNDING
+}
+
+pub(crate) fn to_ndjson_line(row: &Checkin) -> String {
+    let record = serde_json::json!({
+        "location_name": row.location_name,
+        "lat": round_coord(row.gps.y),
+        "lng": round_coord(row.gps.x),
+        "crowded_level": row.crowded_level,
+        "missing_goods": row.missing_goods,
+        "created_at": row.created_at,
+    });
+    format!("{}\n", record)
+}
+
+fn csv_escape(value: &str) -> String {
+    if value.contains(',') || value.contains('"') {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+pub(crate) fn to_csv_line(row: &Checkin) -> String {
+    format!(
+        "{},{},{},{},\"{}\",{}\n",
+        csv_escape(&row.location_name),
+        round_coord(row.gps.y),
+        round_coord(row.gps.x),
+        row.crowded_level,
+        row.missing_goods.join(";"),
+        row.created_at,
+    )
+}
+
+pub(crate) const CSV_HEADER: &str = "location_name,lat,lng,crowded_level,missing_goods,created_at\n";
+
+pub async fn export_checkins(query: ExportQuery, db: Db) -> Result<impl Reply, Rejection> {
+    if query.to <= query.from {
+        return Err(warp::reject::custom(ApiError::Validation(
+            "to must be after from".to_string(),
+        )));
+    }
+    let as_csv = query.format == "csv";
+    let from = query.from;
+    let to = query.to;
+
+    let fetch_page = move |after_id: Option<i32>, limit: i64| {
+        let db = db.clone();
+        Box::pin(async move {
+            db.run(move |conn| {
+                use crate::schema::checkins::dsl::*;
+                checkins
+                    .filter(id.gt(after_id.unwrap_or(0)))
+                    .filter(created_at.ge(from))
+                    .filter(created_at.lt(to))
+                    .filter(hidden.eq(false))
+                    .order(id.asc())
+                    .limit(limit)
+                    .load::<Checkin>(conn)
+            })
+            .await
+            .map(|rows| {
+                rows.iter()
+                    .map(|row| {
+                        let line = if as_csv {
+                            to_csv_line(row)
+                        } else {
+                            to_ndjson_line(row)
+                        };
+                        (row.id, line)
+                    })
+                    .collect()
+            })
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))
+        }) as BoxFuture<'static, PageResult>
+    };
+
+    let header: Vec<io::Result<Bytes>> = if as_csv {
+        vec![Ok(Bytes::from(CSV_HEADER))]
+    } else {
+        vec![]
+    };
+    let body = Body::wrap_stream(stream::iter(header).chain(streaming::line_stream(fetch_page)));
+
+    let content_type = if as_csv {
+        "text/csv"
+    } else {
+        "application/x-ndjson"
+    };
+    let response = Response::builder()
+        .header("Content-Type", content_type)
+        .header("Transfer-Encoding", "chunked")
+        .body(body)
+        .expect("build export response");
+    Ok(response)
+}
+
+/// Synchronous counterpart to [`export_checkins`] for the `export` CLI
+/// subcommand, which has no warp request/response cycle to stream a body
+/// through. Same keyset pagination, same anonymization, just written
+/// straight to stdout.
+pub fn export_to_stdout(
+    conn: &PgConnection,
+    format: &str,
+    from: NaiveDateTime,
+    to: NaiveDateTime,
+) -> QueryResult<()> {
+    use crate::schema::checkins::dsl;
+
+    let as_csv = format == "csv";
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    if as_csv {
+        out.write_all(CSV_HEADER.as_bytes())
+            .expect("write export header to stdout");
+    }
+
+    let mut after_id = 0;
+    loop {
+        let rows: Vec<Checkin> = dsl::checkins
+            .filter(dsl::id.gt(after_id))
+            .filter(dsl::created_at.ge(from))
+            .filter(dsl::created_at.lt(to))
+            .filter(dsl::hidden.eq(false))
+            .order(dsl::id.asc())
+            .limit(EXPORT_PAGE_SIZE)
+            .load(conn)?;
+        if rows.is_empty() {
+            break;
+        }
+        for row in &rows {
+            let line = if as_csv {
+                to_csv_line(row)
+            } else {
+                to_ndjson_line(row)
+            };
+            out.write_all(line.as_bytes())
+                .expect("write export line to stdout");
+        }
+        after_id = rows.last().expect("checked non-empty above").id;
+    }
+    Ok(())
+}
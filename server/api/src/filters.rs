@@ -1,58 +1,512 @@
 use super::handlers;
-use super::model::{CheckinsAroundRequest, NewJsonCheckin};
-use super::Pool;
+use super::model::{
+    CheckinsAroundRequest, DeleteCheckinQuery, ListCheckinsQuery, NearbyQuery, NewJsonCheckin,
+    NewLocationJson, UpdateCheckinJson,
+};
+use crate::analytics::{self, HeatmapQuery};
+use crate::auth::bearer_auth;
+use crate::blobs::SharedBlobStore;
+use crate::cache::Cache;
+use crate::db::Db;
+use crate::export::{self, ExportQuery};
+use crate::forecast;
+use crate::geocoding::SharedGeocoder;
+use crate::goods;
+use crate::leaderboard::{self, LeaderboardQuery};
+use crate::locations;
+use crate::model::{NewWatchJson, RegisterHandleJson};
+use crate::photos;
+use crate::pseudonymize::SharedPseudonymizer;
+use crate::push::SharedPushProvider;
+use crate::rate_limit;
+use crate::stats::{self, StatsQuery};
+use crate::status;
+use crate::sync::{self, SyncQuery};
+use crate::v2::{self, NewJsonCheckinV2};
+use crate::watches;
+use crate::ws;
 use serde::de::DeserializeOwned;
 use warp::Filter;
 
+/// The `/v2` tree, kept separate from `checkins()` below so each API version
+/// can evolve its own route set without the two colliding on `warp::path`.
+pub fn checkins_v2(
+    db: Db,
+    body_limit_bytes: u64,
+    checkin_rate_limit_per_minute: u32,
+    ws_registry: ws::Registry,
+    cache: Cache,
+    pseudonymizer: SharedPseudonymizer,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path("v2").and(checkins_create_v2(
+        db,
+        body_limit_bytes,
+        checkin_rate_limit_per_minute,
+        ws_registry,
+        cache,
+        pseudonymizer,
+    ))
+}
+
+fn checkins_create_v2(
+    db: Db,
+    body_limit_bytes: u64,
+    rate_limit_per_minute: u32,
+    ws_registry: ws::Registry,
+    cache: Cache,
+    pseudonymizer: SharedPseudonymizer,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path("checkins")
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(crate::request_id::filter())
+        .and(bearer_auth(db.clone()))
+        .and_then(move |request_id: String, client: crate::auth::AuthenticatedClient| async move {
+            rate_limit::check(&client.client_id, rate_limit_per_minute)?;
+            Ok::<_, warp::Rejection>((request_id, client))
+        })
+        .untuple_one()
+        .and(json_body::<NewJsonCheckinV2>(body_limit_bytes))
+        .and(with_db(db))
+        .and(warp::any().map(move || ws_registry.clone()))
+        .and(crate::cache::filter(cache))
+        .and(crate::pseudonymize::filter(pseudonymizer))
+        .and_then(v2::create_checkin)
+}
+
 pub fn checkins(
-    db: Pool,
+    db: Db,
+    body_limit_bytes: u64,
+    checkin_rate_limit_per_minute: u32,
+    ws_registry: ws::Registry,
+    cache: Cache,
+    geocoder: SharedGeocoder,
+    push_provider: SharedPushProvider,
+    blob_store: SharedBlobStore,
+    photo_limit_bytes: u64,
+    pseudonymizer: SharedPseudonymizer,
 ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
     warp::path("v1").and(
-        checkins_around(db.clone())
+        checkins_around(db.clone(), body_limit_bytes)
+            .or(checkins_nearby(db.clone()))
+            .or(checkins_geojson(db.clone()))
+            .or(checkins_clusters(db.clone()))
             .or(checkins_list(db.clone()))
-            .or(checkins_create(db.clone())),
+            .or(checkins_create(
+                db.clone(),
+                body_limit_bytes,
+                checkin_rate_limit_per_minute,
+                ws_registry,
+                cache.clone(),
+                geocoder,
+                push_provider,
+                pseudonymizer.clone(),
+            ))
+            .or(checkins_batch_create(
+                db.clone(),
+                body_limit_bytes,
+                cache.clone(),
+                pseudonymizer.clone(),
+            ))
+            .or(checkins_get(db.clone()))
+            .or(checkins_update(
+                db.clone(),
+                body_limit_bytes,
+                pseudonymizer.clone(),
+                cache.clone(),
+            ))
+            .or(checkins_delete(db.clone(), pseudonymizer.clone(), cache.clone()))
+            .or(checkins_photo_upload(
+                db.clone(),
+                blob_store.clone(),
+                photo_limit_bytes,
+            ))
+            .or(checkins_photo_get(db.clone(), blob_store))
+            .or(location_stats(db.clone(), cache.clone()))
+            .or(location_forecast(db.clone()))
+            .or(location_status(db.clone()))
+            .or(locations_list(db.clone()))
+            .or(locations_create(db.clone(), body_limit_bytes))
+            .or(goods_list(db.clone()))
+            .or(goods_search(db.clone()))
+            .or(heatmap(db.clone(), cache.clone()))
+            .or(leaderboard(db.clone(), cache))
+            .or(users_export(db.clone(), pseudonymizer.clone()))
+            .or(users_erase(db.clone(), pseudonymizer))
+            .or(users_watches_list(db.clone()))
+            .or(users_watches_create(db.clone(), body_limit_bytes))
+            .or(users_watches_delete(db.clone()))
+            .or(users_handle_register(db.clone(), body_limit_bytes))
+            .or(users_achievements(db.clone()))
+            .or(sync_changes(db.clone()))
+            .or(bulk_export(db)),
     )
 }
 
+fn bulk_export(db: Db) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("export")
+        .and(warp::get())
+        .and(warp::query::<ExportQuery>())
+        .and(with_db(db))
+        .and_then(export::export_checkins)
+}
+
+fn sync_changes(db: Db) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("sync")
+        .and(warp::get())
+        .and(warp::query::<SyncQuery>())
+        .and(with_db(db))
+        .and_then(sync::sync)
+}
+
+fn users_export(
+    db: Db,
+    pseudonymizer: SharedPseudonymizer,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("users" / String / "export")
+        .and(warp::get())
+        .and(bearer_auth(db.clone()))
+        .and(with_db(db))
+        .and(crate::pseudonymize::filter(pseudonymizer))
+        .and_then(handlers::export_user_checkins)
+}
+
+fn users_erase(
+    db: Db,
+    pseudonymizer: SharedPseudonymizer,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("users" / String)
+        .and(warp::delete())
+        .and(crate::request_id::filter())
+        .and(bearer_auth(db.clone()))
+        .and(with_db(db))
+        .and(crate::pseudonymize::filter(pseudonymizer))
+        .and_then(handlers::erase_user_checkins)
+}
+
+fn users_watches_list(
+    db: Db,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("users" / String / "watches")
+        .and(warp::get())
+        .and(with_db(db))
+        .and_then(watches::list_watches)
+}
+
+fn users_watches_create(
+    db: Db,
+    body_limit_bytes: u64,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("users" / String / "watches")
+        .and(warp::post())
+        .and(bearer_auth(db.clone()))
+        .and(json_body::<NewWatchJson>(body_limit_bytes))
+        .and(with_db(db))
+        .and_then(watches::create_watch)
+}
+
+fn users_watches_delete(
+    db: Db,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("users" / String / "watches" / i32)
+        .and(warp::delete())
+        .and(with_db(db))
+        .and_then(watches::delete_watch)
+}
+
+fn heatmap(
+    db: Db,
+    cache: Cache,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("heatmap")
+        .and(warp::get())
+        .and(warp::query::<HeatmapQuery>())
+        .and(crate::cache::filter(cache))
+        .and(with_db(db))
+        .and_then(analytics::heatmap)
+}
+
+fn leaderboard(
+    db: Db,
+    cache: Cache,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("leaderboard")
+        .and(warp::get())
+        .and(warp::query::<LeaderboardQuery>())
+        .and(crate::cache::filter(cache))
+        .and(with_db(db))
+        .and_then(leaderboard::leaderboard)
+}
+
+fn users_handle_register(
+    db: Db,
+    body_limit_bytes: u64,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("users" / String / "handle")
+        .and(warp::post())
+        .and(json_body::<RegisterHandleJson>(body_limit_bytes))
+        .and(with_db(db))
+        .and_then(leaderboard::register_handle)
+}
+
+fn users_achievements(
+    db: Db,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("users" / String / "achievements")
+        .and(warp::get())
+        .and(with_db(db))
+        .and_then(leaderboard::achievements)
+}
+
+fn goods_list(db: Db) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path("goods")
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(with_db(db))
+        .and_then(goods::list_goods)
+}
+
+fn goods_search(db: Db) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("goods" / "search")
+        .and(warp::get())
+        .and(warp::query::<goods::GoodsSearchQuery>())
+        .and(with_db(db))
+        .and_then(goods::search_goods)
+}
+
+fn locations_list(db: Db) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path("locations")
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(warp::query::<locations::ListLocationsQuery>())
+        .and(with_db(db))
+        .and_then(locations::list_locations)
+}
+
+fn locations_create(
+    db: Db,
+    body_limit_bytes: u64,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path("locations")
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(json_body::<NewLocationJson>(body_limit_bytes))
+        .and(with_db(db))
+        .and_then(locations::create_location)
+}
+
+fn location_stats(
+    db: Db,
+    cache: Cache,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("locations" / String / "stats")
+        .and(warp::get())
+        .and(warp::query::<StatsQuery>())
+        .and(warp::header::optional::<String>("if-none-match"))
+        .and(crate::cache::filter(cache))
+        .and(with_db(db))
+        .and_then(stats::location_stats)
+}
+
+fn location_forecast(
+    db: Db,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("locations" / i32 / "forecast")
+        .and(warp::get())
+        .and(with_db(db))
+        .and_then(forecast::location_forecast)
+}
+
+fn location_status(
+    db: Db,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("locations" / i32 / "status")
+        .and(warp::get())
+        .and(with_db(db))
+        .and_then(status::location_status)
+}
+
+fn checkins_get(
+    db: Db,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("checkins" / i32)
+        .and(warp::get())
+        .and(with_db(db))
+        .and_then(handlers::get_checkin)
+}
+
+fn checkins_update(
+    db: Db,
+    body_limit_bytes: u64,
+    pseudonymizer: SharedPseudonymizer,
+    cache: Cache,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("checkins" / i32)
+        .and(warp::put())
+        .and(crate::request_id::filter())
+        .and(json_body::<UpdateCheckinJson>(body_limit_bytes))
+        .and(with_db(db))
+        .and(crate::pseudonymize::filter(pseudonymizer))
+        .and(crate::cache::filter(cache))
+        .and_then(handlers::update_checkin)
+}
+
+fn checkins_photo_upload(
+    db: Db,
+    blob_store: SharedBlobStore,
+    photo_limit_bytes: u64,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("checkins" / i32 / "photo")
+        .and(warp::post())
+        .and(bearer_auth(db.clone()))
+        .and(with_db(db))
+        .and(with_blob_store(blob_store))
+        .and(warp::multipart::form().max_length(photo_limit_bytes))
+        .and_then(photos::upload_photo)
+}
+
+fn checkins_photo_get(
+    db: Db,
+    blob_store: SharedBlobStore,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("checkins" / i32 / "photo")
+        .and(warp::get())
+        .and(with_db(db))
+        .and(with_blob_store(blob_store))
+        .and_then(photos::get_photo)
+}
+
+fn checkins_delete(
+    db: Db,
+    pseudonymizer: SharedPseudonymizer,
+    cache: Cache,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("checkins" / i32)
+        .and(warp::delete())
+        .and(crate::request_id::filter())
+        .and(warp::query::<DeleteCheckinQuery>())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(with_db(db))
+        .and(crate::pseudonymize::filter(pseudonymizer))
+        .and(crate::cache::filter(cache))
+        .and_then(handlers::delete_checkin)
+}
+
+fn checkins_nearby(
+    db: Db,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("checkins" / "nearby")
+        .and(warp::get())
+        .and(warp::query::<NearbyQuery>())
+        .and(with_db(db))
+        .and_then(handlers::checkins_nearby)
+}
+
+fn checkins_geojson(
+    db: Db,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("checkins" / "geojson")
+        .and(warp::get())
+        .and(with_db(db))
+        .and_then(handlers::geojson_checkins)
+}
+
+fn checkins_clusters(
+    db: Db,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("checkins" / "clusters")
+        .and(warp::get())
+        .and(with_db(db))
+        .and_then(handlers::checkins_clusters)
+}
+
 
 fn checkins_around(
-    db: Pool,
+    db: Db,
+    body_limit_bytes: u64,
 ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
     warp::path!("checkins" / "around")
         .and(warp::post())
-        .and(json_body::<CheckinsAroundRequest>())
+        .and(json_body::<CheckinsAroundRequest>(body_limit_bytes))
         .and(with_db(db))
         .and_then(handlers::checkins_around)
 }
 
 fn checkins_list(
-    db: Pool,
+    db: Db,
 ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
     warp::path("checkins")
+        .and(warp::path::end())
         .and(warp::get())
+        .and(warp::query::<ListCheckinsQuery>())
+        .and(warp::header::optional::<String>("if-none-match"))
         .and(with_db(db))
         .and_then(handlers::list_checkins)
 }
 
 fn checkins_create(
-    db: Pool,
+    db: Db,
+    body_limit_bytes: u64,
+    rate_limit_per_minute: u32,
+    ws_registry: ws::Registry,
+    cache: Cache,
+    geocoder: SharedGeocoder,
+    push_provider: SharedPushProvider,
+    pseudonymizer: SharedPseudonymizer,
 ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
     warp::path("checkins")
+        .and(warp::path::end())
         .and(warp::post())
-        .and(json_body::<NewJsonCheckin>())
+        .and(crate::request_id::filter())
+        .and(bearer_auth(db.clone()))
+        .and_then(move |request_id: String, client: crate::auth::AuthenticatedClient| async move {
+            rate_limit::check(&client.client_id, rate_limit_per_minute)?;
+            Ok::<_, warp::Rejection>((request_id, client))
+        })
+        .untuple_one()
+        .and(warp::header::optional::<String>("idempotency-key"))
+        .and(json_body::<NewJsonCheckin>(body_limit_bytes))
         .and(with_db(db))
+        .and(warp::any().map(move || ws_registry.clone()))
+        .and(crate::cache::filter(cache))
+        .and(crate::geocoding::filter(geocoder))
+        .and(crate::push::filter(push_provider))
+        .and(crate::pseudonymize::filter(pseudonymizer))
         .and_then(handlers::create_checkin)
 }
 
-fn json_body<T>() -> impl Filter<Extract = (T,), Error = warp::Rejection> + Clone
+fn checkins_batch_create(
+    db: Db,
+    body_limit_bytes: u64,
+    cache: Cache,
+    pseudonymizer: SharedPseudonymizer,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("checkins" / "batch")
+        .and(warp::post())
+        .and(crate::request_id::filter())
+        .and(bearer_auth(db.clone()))
+        .and(json_body::<Vec<NewJsonCheckin>>(body_limit_bytes * 32))
+        .and(with_db(db))
+        .and(crate::cache::filter(cache))
+        .and(crate::pseudonymize::filter(pseudonymizer))
+        .and_then(handlers::create_checkins_batch)
+}
+
+fn json_body<T>(limit_bytes: u64) -> impl Filter<Extract = (T,), Error = warp::Rejection> + Clone
 where
     T: DeserializeOwned + Send,
 {
     // When accepting a body, we want a JSON body
     // (and to reject huge payloads)...
-    warp::body::content_length_limit(1024 * 16).and(warp::body::json())
+    warp::body::content_length_limit(limit_bytes).and(warp::body::json())
 }
 
-fn with_db(db: Pool) -> impl Filter<Extract = (Pool,), Error = std::convert::Infallible> + Clone {
+fn with_db(db: Db) -> impl Filter<Extract = (Db,), Error = std::convert::Infallible> + Clone {
     warp::any().map(move || db.clone())
 }
+
+fn with_blob_store(
+    blob_store: SharedBlobStore,
+) -> impl Filter<Extract = (SharedBlobStore,), Error = std::convert::Infallible> + Clone {
+    warp::any().map(move || blob_store.clone())
+}
@@ -0,0 +1,199 @@
+//! Deterministic demo data used by `cargo run -- seed-demo` and by tests that
+//! want a realistic, reproducible scenario without standing up the admin
+//! tooling. Every function here is a pure generator keyed off `SEED`, so two
+//! runs always produce the same locations, timestamps and shortages.
+//!
+//! This only touches the `checkins` table as it exists today, inserting rows
+//! with `location_id` left unset and `hidden` false; demo data isn't run
+//! through location resolution or moderation.
+
+use crate::model::NewCheckin;
+use crate::schema::checkins;
+use chrono::{Duration, Utc};
+use diesel::prelude::*;
+use diesel_geography::types::GeogPoint;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+const SEED: u64 = 2020_03_22;
+/// Roughly Hamburg, matching the coordinates already used in the handler tests.
+const CITY_CENTER: (f64, f64) = (9.97, 53.55);
+const NUM_LOCATIONS: usize = 20;
+const SIM_DAYS: i64 = 14;
+const CHECKIN_HOURS: [u32; 4] = [8, 12, 17, 20];
+/// A sentinel row that marks a database as already seeded, so re-running the
+/// command is a no-op instead of duplicating the whole dataset.
+const MARKER_LOCATION: &str = "__seed_demo_marker__";
+
+const GOODS: &[&str] = &[
+    "flour",
+    "toilet paper",
+    "pasta",
+    "yeast",
+    "disinfectant",
+    "rice",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DemoLocation {
+    pub name: String,
+    pub gps: (f64, f64),
+}
+
+/// Generates `count` locations scattered around `center`, deterministic for a given seed.
+pub fn demo_locations(seed: u64, center: (f64, f64), count: usize) -> Vec<DemoLocation> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    (0..count)
+        .map(|i| {
+            let dx: f64 = rng.gen_range(-0.05, 0.05);
+            let dy: f64 = rng.gen_range(-0.05, 0.05);
+            DemoLocation {
+                name: format!("Demo Market #{}", i + 1),
+                gps: (center.0 + dx, center.1 + dy),
+            }
+        })
+        .collect()
+}
+
+/// Lunchtime and after-work rushes are busier than the rest of the day.
+fn crowded_level_for_hour(rng: &mut StdRng, hour: u32) -> i32 {
+    let base = match hour {
+        12 | 13 => 4,
+        17 | 18 => 5,
+        _ => 2,
+    };
+    (base + rng.gen_range(0, 2)).min(5)
+}
+
+/// Shortages come and go every few days rather than affecting every good at once.
+fn shortages_for_day(rng: &mut StdRng, day: i64) -> Vec<String> {
+    let spike = day % 5 == 0;
+    GOODS
+        .iter()
+        .filter(|_| rng.gen_bool(if spike { 0.4 } else { 0.1 }))
+        .map(|good| good.to_string())
+        .collect()
+}
+
+fn marker_row() -> NewCheckin {
+    NewCheckin {
+        gps: GeogPoint {
+            x: CITY_CENTER.0,
+            y: CITY_CENTER.1,
+            srid: None,
+        },
+        location_name: MARKER_LOCATION.to_string(),
+        crowded_level: 0,
+        missing_goods: vec![],
+        user_id: "seed-demo".to_string(),
+        client_id: "seed-demo".to_string(),
+        created_at: Utc::now().naive_utc(),
+        location_id: None,
+        updated_at: Utc::now().naive_utc(),
+        hidden: false,
+        note: None,
+        observed_at: Utc::now().naive_utc(),
+        idempotency_key: None,
+        geocoded_address: None,
+    }
+}
+
+/// Builds the full two-week dataset (without inserting it), so tests can
+/// assert on its shape without touching the database.
+pub fn demo_checkins() -> Vec<NewCheckin> {
+    let mut rng = StdRng::seed_from_u64(SEED);
+    let locations = demo_locations(SEED, CITY_CENTER, NUM_LOCATIONS);
+    let start = Utc::now().naive_utc() - Duration::days(SIM_DAYS);
+
+    let mut rows = Vec::new();
+    for day in 0..SIM_DAYS {
+        let missing_goods = shortages_for_day(&mut rng, day);
+        for hour in CHECKIN_HOURS.iter() {
+            for loc in &locations {
+                rows.push(NewCheckin {
+                    gps: GeogPoint {
+                        x: loc.gps.0,
+                        y: loc.gps.1,
+                        srid: None,
+                    },
+                    location_name: loc.name.clone(),
+                    crowded_level: crowded_level_for_hour(&mut rng, *hour),
+                    missing_goods: missing_goods.clone(),
+                    user_id: format!("demo-user-{}", rng.gen_range(0, 50)),
+                    client_id: "seed-demo".to_string(),
+                    created_at: start + Duration::days(day) + Duration::hours(i64::from(*hour)),
+                    location_id: None,
+                    updated_at: start + Duration::days(day) + Duration::hours(i64::from(*hour)),
+                    hidden: false,
+                    note: None,
+                    observed_at: start + Duration::days(day) + Duration::hours(i64::from(*hour)),
+                    idempotency_key: None,
+                    geocoded_address: None,
+                });
+            }
+        }
+    }
+    rows.push(marker_row());
+    rows
+}
+
+/// True once the marker row from a previous `seed()` call is present.
+pub fn is_seeded(conn: &PgConnection) -> QueryResult<bool> {
+    use crate::schema::checkins::dsl::*;
+    let count: i64 = checkins
+        .filter(location_name.eq(MARKER_LOCATION))
+        .count()
+        .get_result(conn)?;
+    Ok(count > 0)
+}
+
+/// Inserts the demo dataset. Idempotent: skips if the marker row is already present.
+///
+/// Note: this assumes migrations have already been applied (`diesel migration run`);
+/// running them automatically is tracked separately.
+pub fn seed(conn: &PgConnection) -> QueryResult<usize> {
+    if is_seeded(conn)? {
+        info!("demo data already present, skipping seed");
+        return Ok(0);
+    }
+    let rows = demo_checkins();
+    diesel::insert_into(checkins::table)
+        .values(&rows)
+        .execute(conn)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn demo_locations_are_deterministic() {
+        let a = demo_locations(SEED, CITY_CENTER, NUM_LOCATIONS);
+        let b = demo_locations(SEED, CITY_CENTER, NUM_LOCATIONS);
+        assert_eq!(a, b);
+        assert_eq!(a.len(), NUM_LOCATIONS);
+    }
+
+    #[test]
+    fn demo_checkins_cover_every_location_and_include_a_marker() {
+        let rows = demo_checkins();
+        let distinct_locations: std::collections::HashSet<_> =
+            rows.iter().map(|r| r.location_name.clone()).collect();
+        // +1 for the marker row's own location name.
+        assert_eq!(distinct_locations.len(), NUM_LOCATIONS + 1);
+        assert!(rows.iter().any(|r| r.location_name == MARKER_LOCATION));
+        assert_eq!(
+            rows.len(),
+            NUM_LOCATIONS * SIM_DAYS as usize * CHECKIN_HOURS.len() + 1
+        );
+    }
+
+    #[test]
+    fn shortages_never_exceed_the_goods_catalog() {
+        let mut rng = StdRng::seed_from_u64(SEED);
+        for day in 0..SIM_DAYS {
+            let shortages = shortages_for_day(&mut rng, day);
+            assert!(shortages.len() <= GOODS.len());
+        }
+    }
+}
@@ -0,0 +1,145 @@
+//! "Best time to shop" forecast for a location: buckets historical checkins
+//! by `(weekday, hour)` and predicts the next 24 hours from each hour's
+//! rolling average, the same idea as Google's popular-times chart.
+
+use crate::db::Db;
+use crate::errors::ApiError;
+use crate::model::Checkin;
+use chrono::{Datelike, Duration, NaiveDateTime, Timelike, Utc};
+use diesel::prelude::*;
+use serde::Serialize;
+use std::collections::HashMap;
+use warp::{Rejection, Reply};
+
+/// How far back to look when building the per-hour-of-week averages.
+const HISTORY_WEEKS: i64 = 8;
+const FORECAST_HOURS: i64 = 24;
+
+#[derive(Debug, Serialize)]
+pub struct ForecastHour {
+    pub hour: NaiveDateTime,
+    pub predicted_crowded_level: f64,
+    /// How many historical checkins fed this hour's prediction -- 0 means it
+    /// fell back to the overall average for lack of data at that bucket.
+    pub sample_size: i64,
+}
+
+pub async fn location_forecast(location_id: i32, db: Db) -> Result<impl Reply, Rejection> {
+    let rows: Vec<Checkin> = db
+        .run(move |conn| {
+            use crate::schema::checkins::dsl;
+            let since = Utc::now().naive_utc() - Duration::weeks(HISTORY_WEEKS);
+            dsl::checkins
+                .filter(dsl::location_id.eq(location_id))
+                .filter(dsl::observed_at.ge(since))
+                .filter(dsl::hidden.eq(false))
+                .load(conn)
+        })
+        .await
+        .map_err(ApiError::from)
+        .map_err(warp::reject::custom)?;
+
+    if rows.is_empty() {
+        return Err(warp::reject::custom(ApiError::NotFound));
+    }
+
+    Ok(warp::reply::json(&build_forecast(&rows, Utc::now().naive_utc())))
+}
+
+/// `(weekday as 0=Monday..6=Sunday, hour)`, shared by the historical
+/// averages and the next-24-hours lookup so both use the same bucketing.
+fn bucket_key(dt: NaiveDateTime) -> (u32, u32) {
+    (dt.weekday().num_days_from_monday(), dt.hour())
+}
+
+fn build_forecast(rows: &[Checkin], now: NaiveDateTime) -> Vec<ForecastHour> {
+    let mut buckets: HashMap<(u32, u32), (i64, i64)> = HashMap::new();
+    for row in rows {
+        let entry = buckets.entry(bucket_key(row.observed_at)).or_insert((0, 0));
+        entry.0 += row.crowded_level as i64;
+        entry.1 += 1;
+    }
+
+    let overall_average =
+        rows.iter().map(|r| r.crowded_level as i64).sum::<i64>() as f64 / rows.len() as f64;
+
+    (1..=FORECAST_HOURS)
+        .map(|offset| {
+            let target = now + Duration::hours(offset);
+            let hour = target.date().and_hms(target.hour(), 0, 0);
+            let (predicted_crowded_level, sample_size) = match buckets.get(&bucket_key(hour)) {
+                Some((sum, count)) => (*sum as f64 / *count as f64, *count),
+                None => (overall_average, 0),
+            };
+            ForecastHour {
+                hour,
+                predicted_crowded_level,
+                sample_size,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use diesel_geography::types::GeogPoint;
+
+    fn checkin_at(observed_at: NaiveDateTime, crowded_level: i32) -> Checkin {
+        Checkin {
+            id: 0,
+            gps: GeogPoint {
+                x: 0.0,
+                y: 0.0,
+                srid: None,
+            },
+            location_name: "Demo Market".to_string(),
+            crowded_level,
+            missing_goods: vec![],
+            user_id: "u".to_string(),
+            client_id: "c".to_string(),
+            created_at: observed_at,
+            location_id: Some(1),
+            updated_at: observed_at,
+            hidden: false,
+            note: None,
+            observed_at,
+            idempotency_key: None,
+            geocoded_address: None,
+            region: "u0".to_string(),
+        }
+    }
+
+    #[test]
+    fn predicts_from_same_hour_last_week() {
+        use chrono::NaiveDate;
+        // 2020-07-13 is a Monday.
+        let last_monday_9am = NaiveDate::from_ymd(2020, 7, 13).and_hms(9, 0, 0);
+        let rows = vec![
+            checkin_at(last_monday_9am, 5),
+            checkin_at(last_monday_9am, 3),
+        ];
+        // now = the following Monday at midnight, so offset 9 lands on 9am.
+        let now = NaiveDate::from_ymd(2020, 7, 20).and_hms(0, 0, 0);
+
+        let forecast = build_forecast(&rows, now);
+        let nine_am = &forecast[8];
+        assert_eq!(nine_am.hour, NaiveDate::from_ymd(2020, 7, 20).and_hms(9, 0, 0));
+        assert!((nine_am.predicted_crowded_level - 4.0).abs() < f64::EPSILON);
+        assert_eq!(nine_am.sample_size, 2);
+    }
+
+    #[test]
+    fn falls_back_to_overall_average_without_bucket_data() {
+        use chrono::NaiveDate;
+        let monday_9am = NaiveDate::from_ymd(2020, 7, 13).and_hms(9, 0, 0);
+        let rows = vec![checkin_at(monday_9am, 2), checkin_at(monday_9am, 4)];
+        let now = NaiveDate::from_ymd(2020, 7, 13).and_hms(0, 0, 0);
+
+        let forecast = build_forecast(&rows, now);
+        // Hour 1 (01:00) has no historical data at all.
+        let one_am = &forecast[0];
+        assert!((one_am.predicted_crowded_level - 3.0).abs() < f64::EPSILON);
+        assert_eq!(one_am.sample_size, 0);
+    }
+}
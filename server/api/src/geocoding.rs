@@ -0,0 +1,96 @@
+//! Optional reverse-geocoding of a checkin's GPS point into a structured
+//! address, to clean up the garbage free-text `location_name` users type in
+//! (e.g. "the rewe near me"). Deliberately pluggable: `Geocoder` returns a
+//! `BoxFuture` directly rather than depending on `async-trait`, matching the
+//! async-callback convention [`crate::streaming`] already uses. Enrichment
+//! runs in a detached task after the checkin response is sent (see
+//! `handlers::create_checkin`), so a slow or unreachable geocoding provider
+//! never adds latency to `POST /v1/checkins`.
+
+use futures::future::BoxFuture;
+use serde::Deserialize;
+use std::fmt;
+use std::sync::Arc;
+use warp::Filter;
+
+#[derive(Debug)]
+pub enum GeocodingError {
+    Http(reqwest::Error),
+}
+
+impl fmt::Display for GeocodingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GeocodingError::Http(e) => write!(f, "geocoding request failed: {}", e),
+        }
+    }
+}
+
+impl From<reqwest::Error> for GeocodingError {
+    fn from(e: reqwest::Error) -> Self {
+        GeocodingError::Http(e)
+    }
+}
+
+pub trait Geocoder: Send + Sync {
+    /// Resolves `(lat, lng)` to a display address, or `None` if the provider
+    /// has nothing for that point.
+    fn reverse_geocode(&self, lat: f64, lng: f64) -> BoxFuture<'static, Result<Option<String>, GeocodingError>>;
+}
+
+/// Shared, optionally-absent geocoder injected into filters the same way
+/// `Cache`/`Db` are. `None` means the subsystem is disabled, e.g. because no
+/// provider is configured for this deployment.
+pub type SharedGeocoder = Option<Arc<dyn Geocoder>>;
+
+#[derive(Deserialize)]
+struct NominatimResponse {
+    display_name: Option<String>,
+}
+
+/// Reverse geocodes against a Nominatim-compatible endpoint (the public
+/// OpenStreetMap instance by default, or a self-hosted one for volume).
+/// Nominatim's usage policy requires a `User-Agent` identifying the
+/// application, so that's sent on every request.
+pub struct NominatimGeocoder {
+    base_url: String,
+    client: reqwest::Client,
+}
+
+impl NominatimGeocoder {
+    pub fn new(base_url: String) -> Self {
+        NominatimGeocoder {
+            base_url,
+            client: reqwest::Client::new(),
+        }
+    }
+}
+
+impl Geocoder for NominatimGeocoder {
+    fn reverse_geocode(&self, lat: f64, lng: f64) -> BoxFuture<'static, Result<Option<String>, GeocodingError>> {
+        let url = format!("{}/reverse", self.base_url);
+        let client = self.client.clone();
+        Box::pin(async move {
+            let response = client
+                .get(&url)
+                .query(&[
+                    ("format", "jsonv2"),
+                    ("lat", &lat.to_string()),
+                    ("lon", &lng.to_string()),
+                ])
+                .header("User-Agent", "virus-vs-toilettenpapier/1.0")
+                .send()
+                .await?
+                .error_for_status()?
+                .json::<NominatimResponse>()
+                .await?;
+            Ok(response.display_name)
+        })
+    }
+}
+
+pub fn filter(
+    geocoder: SharedGeocoder,
+) -> impl Filter<Extract = (SharedGeocoder,), Error = std::convert::Infallible> + Clone {
+    warp::any().map(move || geocoder.clone())
+}
@@ -0,0 +1,43 @@
+//! GeoJSON serialization shared by the streaming checkin and cluster endpoints.
+
+use crate::model::Checkin;
+use serde_json::{json, Value};
+
+pub fn checkin_feature(checkin: &Checkin) -> Value {
+    json!({
+        "type": "Feature",
+        "geometry": {
+            "type": "Point",
+            "coordinates": [checkin.gps.x, checkin.gps.y],
+        },
+        "properties": {
+            "id": checkin.id,
+            "location_name": checkin.location_name,
+            "crowded_level": checkin.crowded_level,
+            "missing_goods": checkin.missing_goods,
+            "created_at": checkin.created_at,
+        },
+    })
+}
+
+/// Non-streamed `FeatureCollection` for endpoints that already return a
+/// bounded list (pagination, a radius search) rather than the whole table.
+pub fn feature_collection(checkins: &[Checkin]) -> Value {
+    json!({
+        "type": "FeatureCollection",
+        "features": checkins.iter().map(checkin_feature).collect::<Vec<_>>(),
+    })
+}
+
+pub fn cluster_feature(cluster: &crate::model::ClusterRow) -> Value {
+    json!({
+        "type": "Feature",
+        "geometry": {
+            "type": "Point",
+            "coordinates": [cluster.lng, cluster.lat],
+        },
+        "properties": {
+            "count": cluster.count,
+        },
+    })
+}
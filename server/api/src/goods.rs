@@ -0,0 +1,158 @@
+//! Canonicalizes the free-form `missing_goods` strings reported on a checkin
+//! into a `goods` catalog via `checkin_goods`, so "toilet paper", "Toilet
+//! Paper" and "Klopapier" aggregate into one shortage count instead of
+//! three, and backs `GET /v1/goods/search` autocomplete.
+
+use crate::db::Db;
+use crate::errors::ApiError;
+use crate::model::{CheckinGood, Good, GoodShortageCount, NewGood};
+use crate::schema::{checkin_goods, goods};
+use diesel::prelude::*;
+use diesel::sql_types::{Integer, Text};
+use serde::{Deserialize, Serialize};
+use warp::{Rejection, Reply};
+
+/// Known German/English synonyms for the same shortage, `(canonical_name,
+/// aliases)`. `aliases` always includes the canonical name itself so a
+/// lookup only has to check one list. Extend this table as new synonyms
+/// show up in reports rather than teaching the app another language.
+const ALIAS_TABLE: &[(&str, &[&str])] = &[
+    ("toilet paper", &["toilet paper", "klopapier", "toilettenpapier", "wc papier"]),
+    ("pasta", &["pasta", "nudeln", "noodles"]),
+    ("flour", &["flour", "mehl"]),
+    ("rice", &["rice", "reis"]),
+    ("yeast", &["yeast", "hefe"]),
+    (
+        "disinfectant",
+        &["disinfectant", "desinfektionsmittel", "hand sanitizer", "handdesinfektion"],
+    ),
+    ("milk", &["milk", "milch"]),
+    ("eggs", &["eggs", "egg", "eier", "ei"]),
+    ("canned goods", &["canned goods", "konserven"]),
+    ("face masks", &["face masks", "masks", "masken", "mundschutz"]),
+];
+
+/// Normalizes a free-text good name for matching: lowercased, trimmed.
+fn normalize(name: &str) -> String {
+    name.trim().to_lowercase()
+}
+
+/// Resolves a normalized name to its canonical name and the full alias list
+/// to store alongside it, via [`ALIAS_TABLE`]. Falls back to treating the
+/// name as its own canonical name with no known aliases.
+fn canonicalize(normalized: &str) -> (String, Vec<String>) {
+    for (canonical_name, aliases) in ALIAS_TABLE {
+        if aliases.contains(&normalized) {
+            return (
+                canonical_name.to_string(),
+                aliases.iter().map(|alias| alias.to_string()).collect(),
+            );
+        }
+    }
+    (normalized.to_string(), vec![])
+}
+
+/// Finds a good whose canonical name or alias list matches `name`
+/// (case-insensitively), or creates a new catalog entry for it.
+fn resolve_or_create(conn: &PgConnection, name: &str) -> QueryResult<i32> {
+    let normalized = normalize(name);
+    let (canonical_name, aliases) = canonicalize(&normalized);
+
+    let existing: Option<Good> = goods::table
+        .filter(
+            goods::canonical_name
+                .eq(&canonical_name)
+                .or(goods::aliases.contains(vec![normalized])),
+        )
+        .first(conn)
+        .optional()?;
+
+    if let Some(good) = existing {
+        return Ok(good.id);
+    }
+
+    let inserted: Good = diesel::insert_into(goods::table)
+        .values(NewGood {
+            canonical_name,
+            aliases,
+        })
+        .get_result(conn)?;
+    Ok(inserted.id)
+}
+
+/// Resolves and links every reported good for `checkin_id`, creating catalog
+/// entries as needed. Called from `create_checkin` in the same transaction.
+pub fn link_missing_goods(
+    conn: &PgConnection,
+    checkin_id: i32,
+    missing_goods: &[String],
+) -> QueryResult<()> {
+    for name in missing_goods {
+        let good_id = resolve_or_create(conn, name)?;
+        diesel::insert_into(checkin_goods::table)
+            .values(CheckinGood {
+                checkin_id,
+                good_id,
+            })
+            .on_conflict_do_nothing()
+            .execute(conn)?;
+    }
+    Ok(())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GoodsSearchQuery {
+    pub q: String,
+}
+
+#[derive(Debug, QueryableByName, Serialize)]
+pub struct GoodMatch {
+    #[sql_type = "Integer"]
+    pub id: i32,
+    #[sql_type = "Text"]
+    pub canonical_name: String,
+}
+
+/// Autocomplete for `missing_goods`: matches `q` against both the catalog's
+/// canonical names and their aliases, so typing "klopapier" surfaces
+/// "toilet paper" without the client needing to know it's an alias.
+pub async fn search_goods(query: GoodsSearchQuery, db: Db) -> Result<impl Reply, Rejection> {
+    let pattern = format!("%{}%", query.q.trim());
+    let results: Vec<GoodMatch> = db
+        .run(move |conn| {
+            diesel::sql_query(
+                "select id, canonical_name from goods \
+                 where canonical_name ilike $1 \
+                    or exists (select 1 from unnest(aliases) alias where alias ilike $1) \
+                 order by canonical_name asc \
+                 limit 20",
+            )
+            .bind::<Text, _>(pattern)
+            .get_results(conn)
+        })
+        .await
+        .map_err(ApiError::from)
+        .map_err(warp::reject::custom)?;
+
+    Ok(warp::reply::json(&results))
+}
+
+pub async fn list_goods(db: Db) -> Result<impl Reply, Rejection> {
+    let results: Vec<GoodShortageCount> = db
+        .run(|conn| {
+            goods::table
+                .left_join(checkin_goods::table)
+                .group_by(goods::id)
+                .select((
+                    goods::canonical_name,
+                    diesel::dsl::sql::<diesel::sql_types::BigInt>("count(checkin_goods.good_id)"),
+                ))
+                .order(goods::canonical_name.asc())
+                .load(conn)
+        })
+        .await
+        .map_err(ApiError::from)
+        .map_err(warp::reject::custom)?;
+
+    Ok(warp::reply::json(&results))
+}
@@ -1,86 +1,832 @@
-use super::Pool;
+use crate::auth::AuthenticatedClient;
+use crate::cache::Cache;
+use crate::caching;
+use crate::db::{Db, DbError};
+use crate::errors::ApiError;
+use crate::geocoding::SharedGeocoder;
+use crate::geojson;
+use crate::pseudonymize::SharedPseudonymizer;
+use crate::push::SharedPushProvider;
 use crate::model::CheckinsAroundRequest;
-use crate::model::{Checkin, NewCheckin, NewJsonCheckin};
+use crate::model::{
+    Checkin, CheckinSort, CheckinTombstone, ClusterRow, DeleteCheckinQuery, ListCheckinsQuery,
+    NearbyQuery, NewCheckin, NewJsonCheckin, Page, UpdateCheckinJson, UPDATE_WINDOW_MINUTES,
+};
 use crate::schema::checkins;
+use crate::streaming;
+use crate::streaming::PageResult;
 use diesel::dsl::sql_query;
-use diesel::sql_types::Int4;
+use diesel::prelude::*;
+use diesel::sql_types::{Double, Int4};
 use diesel::RunQueryDsl;
-use std::convert::Infallible;
-use warp::http::StatusCode;
-
-pub async fn checkins_around(
-    req: CheckinsAroundRequest,
-    pool: Pool,
-) -> Result<impl warp::Reply, Infallible> {
-    pool.get()
-        .and_then(|conn| {
+use futures::future::BoxFuture;
+use hyper::Body;
+use std::io;
+use warp::http::{Response, StatusCode};
+use warp::{Rejection, Reply};
+
+pub async fn checkins_around(req: CheckinsAroundRequest, db: Db) -> Result<impl Reply, Rejection> {
+    let results: Vec<Checkin> = db
+        .run(move |conn| {
             let q = format!(
-                "select * from checkins where ST_DWithin(gps, 'point({} {})', $1, false) offset $2 limit $3",
+                "select * from checkins \
+                 where ST_DWithin(gps, 'point({} {})', $1, false) and not hidden \
+                 offset $2 limit $3",
                 req.gps[0], req.gps[1]
                 );
-            let res: Result<Vec<Checkin>, _> = sql_query(q)
+            sql_query(q)
                 .bind::<Int4, _>(req.radius)
                 .bind::<Int4, _>(req.offset)
                 .bind::<Int4, _>(req.limit)
-                .get_results::<Checkin>(&conn);
-            Ok(warp::reply::with_status(
-                warp::reply::json::<Vec<Checkin>>(&res.unwrap()),
-                StatusCode::OK,
-            ))
+                .get_results::<Checkin>(conn)
         })
-        .or_else(|e| {
-            error!("Failed listing checins {}", &e);
-            Ok(warp::reply::with_status(
-                warp::reply::json(&""),
-                StatusCode::INTERNAL_SERVER_ERROR,
-            ))
+        .await
+        .map_err(ApiError::from)
+        .map_err(warp::reject::custom)?;
+
+    Ok(warp::reply::json(&results))
+}
+
+/// Checkins within `radius` meters of `(lat, lng)`, closest first, for "what's
+/// crowded near me" on the mobile client.
+#[utoipa::path(
+    get,
+    path = "/v1/checkins/nearby",
+    params(NearbyQuery),
+    responses((status = 200, description = "Checkins near the given point, closest first"))
+)]
+pub async fn checkins_nearby(query: NearbyQuery, db: Db) -> Result<impl Reply, Rejection> {
+    let as_geojson = query.format.as_deref() == Some("geojson");
+    let shard_prefix = crate::storage::shard_prefix_for(query.lat, query.lng);
+    let results: Vec<Checkin> = db
+        .run(move |conn| {
+            let q = format!(
+                "select * from checkins \
+                 where region like '{}%' and ST_DWithin(gps, 'point({} {})', $1, false) and not hidden \
+                 order by ST_Distance(gps, 'point({} {})') asc",
+                shard_prefix, query.lng, query.lat, query.lng, query.lat
+            );
+            sql_query(q)
+                .bind::<Int4, _>(query.radius)
+                .get_results::<Checkin>(conn)
         })
+        .await
+        .map_err(ApiError::from)
+        .map_err(warp::reject::custom)?;
+
+    if as_geojson {
+        Ok(warp::reply::json(&geojson::feature_collection(&results)))
+    } else {
+        Ok(warp::reply::json(&results))
+    }
 }
 
-pub async fn list_checkins(pool: Pool) -> Result<impl warp::Reply, Infallible> {
-    pool.get()
-        .and_then(|conn| {
-            use crate::schema::checkins::dsl::checkins;
-            let res = checkins.load(&conn).unwrap();
-            let checkin: Option<&Checkin> = res.first();
-            Ok(warp::reply::with_status(
-                warp::reply::json(&checkin),
-                StatusCode::OK,
-            ))
+#[utoipa::path(
+    get,
+    path = "/v1/checkins",
+    params(ListCheckinsQuery),
+    responses((status = 200, description = "Paginated, filterable list of checkins"))
+)]
+pub async fn list_checkins(
+    query: ListCheckinsQuery,
+    if_none_match: Option<String>,
+    db: Db,
+) -> Result<impl Reply, Rejection> {
+    let page = query.page.max(1);
+    let per_page = query.per_page.clamp(1, 100);
+    let as_geojson = query.format.as_deref() == Some("geojson");
+
+    let (items, total, max_created_at) = db
+        .run(move |conn| {
+            use crate::schema::checkins::dsl::*;
+
+            macro_rules! apply_filters {
+                ($q:expr) => {{
+                    let mut q = $q.filter(hidden.eq(false));
+                    if let Some(since_ts) = query.since {
+                        q = q.filter(created_at.ge(since_ts));
+                    }
+                    if let Some(until_ts) = query.until {
+                        q = q.filter(created_at.lt(until_ts));
+                    }
+                    if let Some(min_level) = query.min_crowded_level {
+                        q = q.filter(crowded_level.ge(min_level));
+                    }
+                    if let Some(ref good) = query.missing_good {
+                        q = q.filter(missing_goods.contains(vec![good.clone()]));
+                    }
+                    if let Some(ref cid) = query.client_id {
+                        q = q.filter(client_id.eq(cid.clone()));
+                    }
+                    q
+                }};
+            }
+
+            let total: i64 = apply_filters!(checkins.into_boxed())
+                .count()
+                .get_result(conn)?;
+            let max_created_at: Option<chrono::NaiveDateTime> = apply_filters!(checkins.into_boxed())
+                .select(diesel::dsl::max(created_at))
+                .first(conn)?;
+
+            let mut q = apply_filters!(checkins.into_boxed());
+            q = match query.sort {
+                CheckinSort::CreatedAt => q.order(created_at.desc()),
+                CheckinSort::CrowdedLevel => q.order(crowded_level.desc()),
+            };
+            let items: Vec<Checkin> = q.limit(per_page).offset((page - 1) * per_page).load(conn)?;
+
+            Ok((items, total, max_created_at))
         })
-        .or_else(|e| {
-            error!("Failed listing checins {}", &e);
-            Ok(warp::reply::with_status(
-                warp::reply::json(&""),
-                StatusCode::INTERNAL_SERVER_ERROR,
-            ))
+        .await
+        .map_err(ApiError::from)
+        .map_err(warp::reject::custom)?;
+
+    let etag = caching::weak_etag(total, max_created_at);
+
+    let next_page = if page * per_page < total {
+        Some(page + 1)
+    } else {
+        None
+    };
+
+    let reply = if as_geojson {
+        warp::reply::json(&geojson::feature_collection(&items))
+    } else {
+        warp::reply::json(&Page {
+            items,
+            total,
+            next_page,
+        })
+    };
+
+    Ok(caching::with_etag(&etag, if_none_match, reply))
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/checkins/{checkin_id}",
+    params(("checkin_id" = i32, Path, description = "Checkin id")),
+    responses(
+        (status = 200, description = "The checkin"),
+        (status = 404, description = "No checkin with that id")
+    )
+)]
+pub async fn get_checkin(checkin_id: i32, db: Db) -> Result<impl Reply, Rejection> {
+    let checkin: Checkin = db
+        .run(move |conn| {
+            use crate::schema::checkins::dsl;
+            dsl::checkins.find(checkin_id).first(conn)
         })
+        .await
+        .map_err(ApiError::from)
+        .map_err(warp::reject::custom)?;
+
+    Ok(warp::reply::json(&checkin))
 }
 
+/// Deleting requires either the matching `user_id` or an admin bearer token, so
+/// a user can retract their own accidental checkin without an account system.
+pub async fn delete_checkin(
+    checkin_id: i32,
+    request_id: String,
+    query: DeleteCheckinQuery,
+    auth_header: Option<String>,
+    db: Db,
+    pseudonymizer: SharedPseudonymizer,
+    cache: Cache,
+) -> Result<impl Reply, Rejection> {
+    let checkin: Checkin = db
+        .run(move |conn| {
+            use crate::schema::checkins::dsl;
+            dsl::checkins.find(checkin_id).first(conn)
+        })
+        .await
+        .map_err(ApiError::from)
+        .map_err(warp::reject::custom)?;
+
+    // Match against every rotation month the checkin could have been
+    // pseudonymized under, not just the current one -- otherwise a checkin
+    // becomes permanently undeletable by its own reporter as soon as the
+    // month rolls over past when it was created.
+    let authorized = crate::auth::is_admin(&auth_header)
+        || match &pseudonymizer {
+            Some(pseudonymizer) => pseudonymizer.candidates(&query.user_id).contains(&checkin.user_id),
+            None => checkin.user_id == query.user_id,
+        };
+
+    if !authorized {
+        return Err(warp::reject::custom(ApiError::Validation(
+            "not authorized to delete this checkin".to_string(),
+        )));
+    }
+
+    let actor = if crate::auth::is_admin(&auth_header) {
+        "admin".to_string()
+    } else {
+        checkin.user_id.clone()
+    };
+    let audit_request_id = request_id.clone();
+
+    db.run(move |conn| {
+        conn.transaction(|| {
+            use crate::schema::checkin_tombstones;
+            use crate::schema::checkins::dsl;
+            diesel::insert_into(checkin_tombstones::table)
+                .values(CheckinTombstone {
+                    checkin_id,
+                    deleted_at: chrono::Utc::now().naive_utc(),
+                })
+                .execute(conn)?;
+            diesel::delete(dsl::checkins.find(checkin_id)).execute(conn)?;
+            crate::audit::record(
+                conn,
+                &actor,
+                "delete",
+                "DELETE /v1/checkins/:id",
+                "checkin",
+                checkin_id,
+                &checkin,
+                &audit_request_id,
+            )
+        })
+    })
+    .await
+    .map_err(ApiError::from)
+    .map_err(warp::reject::custom)?;
+
+    cache.invalidate_all();
+    info!(request_id = %request_id, checkin_id, "deleted checkin");
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Lets the original reporter correct `crowded_level`/`missing_goods` shortly
+/// after creation. `body.expected_updated_at` is an optimistic-concurrency
+/// token: the update only applies if it still matches the row's current
+/// `updated_at`, otherwise the request loses the race and gets a 409.
+pub async fn update_checkin(
+    checkin_id: i32,
+    request_id: String,
+    body: UpdateCheckinJson,
+    db: Db,
+    pseudonymizer: SharedPseudonymizer,
+    cache: Cache,
+) -> Result<impl Reply, Rejection> {
+    let UpdateCheckinJson {
+        user_id,
+        crowded_level,
+        missing_goods,
+        expected_updated_at,
+    } = body;
+    let user_id = match &pseudonymizer {
+        Some(pseudonymizer) => pseudonymizer.apply(&user_id),
+        None => user_id,
+    };
+
+    if let Some(level) = crowded_level {
+        if !(0..=5).contains(&level) {
+            return Err(warp::reject::custom(ApiError::Validation(
+                "crowded_level must be between 0 and 5".to_string(),
+            )));
+        }
+    }
+    if let Some(goods) = &missing_goods {
+        let errors = crate::model::validate_missing_goods(goods);
+        if !errors.is_empty() {
+            return Err(warp::reject::custom(ApiError::Validation(errors.join("; "))));
+        }
+    }
+
+    let existing: Checkin = db
+        .run(move |conn| {
+            use crate::schema::checkins::dsl;
+            dsl::checkins.find(checkin_id).first(conn)
+        })
+        .await
+        .map_err(ApiError::from)
+        .map_err(warp::reject::custom)?;
+
+    if existing.user_id != user_id {
+        return Err(warp::reject::custom(ApiError::Validation(
+            "not authorized to edit this checkin".to_string(),
+        )));
+    }
+    let age = chrono::Utc::now().naive_utc() - existing.created_at;
+    if age > chrono::Duration::minutes(UPDATE_WINDOW_MINUTES) {
+        return Err(warp::reject::custom(ApiError::Validation(format!(
+            "checkins can only be edited within {} minutes of creation",
+            UPDATE_WINDOW_MINUTES
+        ))));
+    }
+
+    let new_crowded_level = crowded_level.unwrap_or(existing.crowded_level);
+    let new_missing_goods = missing_goods.unwrap_or_else(|| existing.missing_goods.clone());
+    let now = chrono::Utc::now().naive_utc();
+
+    let audit_request_id = request_id.clone();
+    let updated: Checkin = db
+        .run(move |conn| {
+            conn.transaction(|| {
+                use crate::schema::checkins::dsl;
+                let updated: Checkin = diesel::update(
+                    dsl::checkins
+                        .filter(dsl::id.eq(checkin_id))
+                        .filter(dsl::updated_at.eq(expected_updated_at)),
+                )
+                .set((
+                    dsl::crowded_level.eq(new_crowded_level),
+                    dsl::missing_goods.eq(new_missing_goods),
+                    dsl::updated_at.eq(now),
+                ))
+                .get_result(conn)?;
+                crate::audit::record(
+                    conn,
+                    &updated.user_id,
+                    "update",
+                    "PUT /v1/checkins/:id",
+                    "checkin",
+                    updated.id,
+                    &updated,
+                    &audit_request_id,
+                )?;
+                Ok(updated)
+            })
+        })
+        .await
+        .map_err(|e| match e {
+            DbError::Query(diesel::result::Error::NotFound) => ApiError::Conflict(
+                "checkin was modified by another request".to_string(),
+            ),
+            other => ApiError::from(other),
+        })
+        .map_err(warp::reject::custom)?;
+
+    cache.invalidate_all();
+    info!(request_id = %request_id, checkin_id, "updated checkin");
+    Ok(warp::reply::json(&updated))
+}
+
+/// Streams every checkin as a GeoJSON `FeatureCollection`, paging through the
+/// table with a keyset query instead of loading it all at once.
+pub async fn geojson_checkins(db: Db) -> Result<impl Reply, Rejection> {
+    let fetch_page = move |after_id: Option<i32>, limit: i64| {
+        let db = db.clone();
+        Box::pin(async move {
+            db.run(move |conn| {
+                use crate::schema::checkins::dsl::*;
+                checkins
+                    .filter(id.gt(after_id.unwrap_or(0)))
+                    .filter(hidden.eq(false))
+                    .order(id.asc())
+                    .limit(limit)
+                    .load::<Checkin>(conn)
+            })
+            .await
+            .map(|rows| {
+                rows.iter()
+                    .map(|row| (row.id, geojson::checkin_feature(row).to_string()))
+                    .collect()
+            })
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))
+        }) as BoxFuture<'static, PageResult>
+    };
+
+    let body = Body::wrap_stream(streaming::json_feature_stream("features", fetch_page));
+    let response = Response::builder()
+        .header("Content-Type", "application/geo+json")
+        .header("Transfer-Encoding", "chunked")
+        .body(body)
+        .expect("build geojson response");
+    Ok(response)
+}
+
+/// Streams a grid-clustered summary (count per cell) as a GeoJSON `FeatureCollection`.
+/// Cluster counts are cheap to compute in one query, so streaming here mostly buys a
+/// uniform response shape with `geojson_checkins` rather than a real memory saving.
+pub async fn checkins_clusters(db: Db) -> Result<impl Reply, Rejection> {
+    let mut served = false;
+    let fetch_page = move |_after: Option<i32>, _limit: i64| {
+        let db = db.clone();
+        let already_served = served;
+        served = true;
+        Box::pin(async move {
+            if already_served {
+                return Ok(vec![]);
+            }
+            db.run(|conn| {
+                sql_query(
+                    "select ST_X(ST_Centroid(ST_Collect(gps::geometry))) as lng, \
+                     ST_Y(ST_Centroid(ST_Collect(gps::geometry))) as lat, \
+                     count(*) as count \
+                     from checkins where not hidden group by ST_SnapToGrid(gps::geometry, $1)",
+                )
+                .bind::<Double, _>(0.01)
+                .get_results::<ClusterRow>(conn)
+            })
+            .await
+            .map(|clusters| {
+                clusters
+                    .iter()
+                    .enumerate()
+                    .map(|(i, row)| (i as i32, geojson::cluster_feature(row).to_string()))
+                    .collect()
+            })
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))
+        }) as BoxFuture<'static, PageResult>
+    };
+
+    let body = Body::wrap_stream(streaming::json_feature_stream("features", fetch_page));
+    let response = Response::builder()
+        .header("Content-Type", "application/geo+json")
+        .header("Transfer-Encoding", "chunked")
+        .body(body)
+        .expect("build clusters response");
+    Ok(response)
+}
+
+/// Inserts up to `model::MAX_BATCH_SIZE` checkins in one transaction for
+/// offline-first clients flushing a queue. A per-item validation failure is
+/// recorded in that item's result rather than aborting the whole batch; only
+/// a real database error rolls everything back.
+pub async fn create_checkins_batch(
+    request_id: String,
+    client: AuthenticatedClient,
+    items: Vec<NewJsonCheckin>,
+    db: Db,
+    cache: Cache,
+    pseudonymizer: SharedPseudonymizer,
+) -> Result<impl Reply, Rejection> {
+    info!(request_id = %request_id, batch_size = items.len(), "create_checkins_batch");
+    if items.len() > crate::model::MAX_BATCH_SIZE {
+        return Err(warp::reject::custom(ApiError::Validation(format!(
+            "batch exceeds the {} item limit",
+            crate::model::MAX_BATCH_SIZE
+        ))));
+    }
+
+    let client_id = client.client_id.clone();
+    let audit_request_id = request_id.clone();
+    let results: Vec<crate::model::BatchItemResult> = db
+        .with_tx(move |conn| {
+            let mut results = Vec::with_capacity(items.len());
+            for (index, mut json_checkin) in items.clone().into_iter().enumerate() {
+                if let Err(errors) = json_checkin.validate() {
+                    results.push(crate::model::BatchItemResult {
+                        index,
+                        status: crate::model::BatchItemStatus::ValidationError,
+                        id: None,
+                        error: Some(errors.join("; ")),
+                    });
+                    continue;
+                }
+                if json_checkin.client_id != client_id {
+                    results.push(crate::model::BatchItemResult {
+                        index,
+                        status: crate::model::BatchItemStatus::ValidationError,
+                        id: None,
+                        error: Some(
+                            "client_id does not match the authenticated API key".to_string(),
+                        ),
+                    });
+                    continue;
+                }
+                if let Some(pseudonymizer) = &pseudonymizer {
+                    json_checkin.user_id = pseudonymizer.apply(&json_checkin.user_id);
+                }
+                if crate::admin::is_banned(conn, &client_id, &json_checkin.user_id)? {
+                    results.push(crate::model::BatchItemResult {
+                        index,
+                        status: crate::model::BatchItemStatus::ValidationError,
+                        id: None,
+                        error: Some("client or user is banned".to_string()),
+                    });
+                    continue;
+                }
+
+                let missing_goods = json_checkin.missing_goods.clone();
+                let mut checkin = NewCheckin::from(json_checkin);
+                checkin.location_id = Some(crate::locations::resolve_or_create(
+                    conn,
+                    &checkin.location_name,
+                    checkin.gps,
+                )?);
+                let inserted: Checkin = diesel::insert_into(checkins::table)
+                    .values(checkin)
+                    .get_result(conn)?;
+                crate::goods::link_missing_goods(conn, inserted.id, &missing_goods)?;
+                crate::reputation::record_agreement(conn, &inserted)?;
+                crate::audit::record(
+                    conn,
+                    &inserted.user_id,
+                    "create",
+                    "POST /v1/checkins/batch",
+                    "checkin",
+                    inserted.id,
+                    &inserted,
+                    &audit_request_id,
+                )?;
+                results.push(crate::model::BatchItemResult {
+                    index,
+                    status: crate::model::BatchItemStatus::Created,
+                    id: Some(inserted.id),
+                    error: None,
+                });
+            }
+            Ok(results)
+        })
+        .await
+        .map_err(ApiError::from)
+        .map_err(warp::reject::custom)?;
+
+    cache.invalidate_all();
+    Ok(warp::reply::json(&results))
+}
+
+/// Resolves `(lat, lng)` to an address in the background and writes it back
+/// to the row once done. Detached from the request/response cycle on
+/// purpose: a slow or down geocoding provider must never delay the client's
+/// 201, and a lookup failure just leaves `geocoded_address` unset rather than
+/// failing the checkin.
+fn spawn_geocode_enrichment(
+    geocoder: std::sync::Arc<dyn crate::geocoding::Geocoder>,
+    db: Db,
+    checkin_id: i32,
+    lat: f64,
+    lng: f64,
+) {
+    tokio::task::spawn(async move {
+        match geocoder.reverse_geocode(lat, lng).await {
+            Ok(Some(address)) => {
+                let result = db
+                    .run(move |conn| {
+                        use crate::schema::checkins::dsl;
+                        diesel::update(dsl::checkins.find(checkin_id))
+                            .set(dsl::geocoded_address.eq(address))
+                            .execute(conn)
+                    })
+                    .await;
+                if let Err(e) = result {
+                    error!(checkin_id, "failed to store geocoded address: {}", e);
+                } else {
+                    info!(checkin_id, "resolved geocoded address");
+                }
+            }
+            Ok(None) => info!(checkin_id, "geocoder returned no address for this point"),
+            Err(e) => error!(checkin_id, "reverse geocoding failed: {}", e),
+        }
+    });
+}
+
+/// Outcome of an insert attempted under an `Idempotency-Key`: either the row
+/// was freshly created, or the key was already used and we're handing back
+/// the checkin it originally created.
+enum CreateCheckinOutcome {
+    Created(Checkin),
+    Replayed(Checkin),
+}
+
+#[utoipa::path(
+    post,
+    path = "/v1/checkins",
+    request_body = NewJsonCheckin,
+    responses(
+        (status = 201, description = "Checkin created"),
+        (status = 200, description = "Idempotency-Key already used, returning the original checkin"),
+        (status = 422, description = "Validation error"),
+        (status = 429, description = "Rate limited")
+    ),
+    security(("bearer_auth" = []))
+)]
 pub async fn create_checkin(
-    json_checkin: NewJsonCheckin,
-    pool: Pool,
-) -> Result<impl warp::Reply, Infallible> {
-    info!("create_checkin");
-    let checkin = NewCheckin::from(json_checkin);
-    pool.get()
-        .and_then(|conn| {
-            let res: Result<Checkin, _> = diesel::insert_into(checkins::table)
+    request_id: String,
+    client: AuthenticatedClient,
+    idempotency_key: Option<String>,
+    mut json_checkin: NewJsonCheckin,
+    db: Db,
+    ws_registry: crate::ws::Registry,
+    cache: Cache,
+    geocoder: SharedGeocoder,
+    push_provider: SharedPushProvider,
+    pseudonymizer: SharedPseudonymizer,
+) -> Result<impl Reply, Rejection> {
+    info!(request_id = %request_id, "create_checkin");
+    if let Err(errors) = json_checkin.validate() {
+        return Err(warp::reject::custom(ApiError::Validation(errors.join("; "))));
+    }
+    if json_checkin.client_id != client.client_id {
+        return Err(warp::reject::custom(ApiError::Validation(
+            "client_id does not match the authenticated API key".to_string(),
+        )));
+    }
+    if let Some(pseudonymizer) = &pseudonymizer {
+        json_checkin.user_id = pseudonymizer.apply(&json_checkin.user_id);
+    }
+    let banned = db
+        .run({
+            let client_id = client.client_id.clone();
+            let user_id = json_checkin.user_id.clone();
+            move |conn| crate::admin::is_banned(conn, &client_id, &user_id)
+        })
+        .await
+        .map_err(ApiError::from)
+        .map_err(warp::reject::custom)?;
+    if banned {
+        return Err(warp::reject::custom(ApiError::Forbidden(
+            "this client or user is banned".to_string(),
+        )));
+    }
+    let mut checkin = NewCheckin::from(json_checkin);
+    checkin.idempotency_key = idempotency_key.clone();
+    let audit_request_id = request_id.clone();
+    let outcome: CreateCheckinOutcome = db
+        .with_tx(move |conn| {
+            let mut checkin = checkin.clone();
+            checkin.location_id = Some(crate::locations::resolve_or_create(
+                conn,
+                &checkin.location_name,
+                checkin.gps,
+            )?);
+            let missing_goods = checkin.missing_goods.clone();
+            let key = checkin.idempotency_key.clone();
+            let client_id = checkin.client_id.clone();
+            match diesel::insert_into(checkins::table)
                 .values(checkin)
-                .get_result(&conn);
-            match res {
-                Ok(checkin) => {
-                    info!("inserted checkin: {:?}", &checkin);
-                    Ok(StatusCode::CREATED)
+                .get_result::<Checkin>(conn)
+            {
+                Ok(inserted) => {
+                    crate::goods::link_missing_goods(conn, inserted.id, &missing_goods)?;
+                    crate::reputation::record_agreement(conn, &inserted)?;
+                    crate::audit::record(
+                        conn,
+                        &inserted.user_id,
+                        "create",
+                        "POST /v1/checkins",
+                        "checkin",
+                        inserted.id,
+                        &inserted,
+                        &audit_request_id,
+                    )?;
+                    if let Some(location_id) = inserted.location_id {
+                        if let Ok(Some(false)) =
+                            crate::locations::is_open_at(conn, location_id, inserted.observed_at)
+                        {
+                            warn!(
+                                checkin_id = inserted.id,
+                                location_id, "checkin reported outside location opening hours"
+                            );
+                        }
+                    }
+                    Ok(CreateCheckinOutcome::Created(inserted))
                 }
-                Err(e) => {
-                    error!("error inserting checkin {}", &e);
-                    Ok(StatusCode::INTERNAL_SERVER_ERROR)
+                Err(diesel::result::Error::DatabaseError(
+                    diesel::result::DatabaseErrorKind::UniqueViolation,
+                    _,
+                )) if key.is_some() => {
+                    use crate::schema::checkins::dsl;
+                    let existing = dsl::checkins
+                        .filter(dsl::client_id.eq(client_id))
+                        .filter(dsl::idempotency_key.eq(key))
+                        .first(conn)?;
+                    Ok(CreateCheckinOutcome::Replayed(existing))
                 }
+                Err(e) => Err(e),
             }
         })
-        .or_else(|e| {
-            error!("error inserting checkin {}", &e);
-            Ok(StatusCode::INTERNAL_SERVER_ERROR)
+        .await
+        .map_err(ApiError::from)
+        .map_err(warp::reject::custom)?;
+
+    match outcome {
+        CreateCheckinOutcome::Created(inserted) => {
+            info!(request_id = %request_id, checkin_id = inserted.id, "inserted checkin");
+            cache.invalidate_all();
+            ws_registry.broadcast(&inserted).await;
+            if let Some(geocoder) = geocoder {
+                spawn_geocode_enrichment(geocoder, db.clone(), inserted.id, inserted.gps.y, inserted.gps.x);
+            }
+            let response = warp::reply::with_status(
+                warp::reply::json(&inserted),
+                StatusCode::CREATED,
+            );
+            if let Some(push_provider) = push_provider {
+                crate::watches::spawn_watch_notifications(push_provider, db, inserted);
+            }
+            Ok(response)
+        }
+        CreateCheckinOutcome::Replayed(existing) => {
+            info!(request_id = %request_id, checkin_id = existing.id, "idempotency key replayed, returning original checkin");
+            Ok(warp::reply::with_status(
+                warp::reply::json(&existing),
+                StatusCode::OK,
+            ))
+        }
+    }
+}
+
+/// GDPR export: every checkin a user submitted through the requesting client,
+/// hidden ones included since it's their own data. Scoped to `client.client_id`
+/// rather than the bare `user_id` path segment, since that's the only proof of
+/// ownership the API key model gives us.
+///
+/// When [`crate::pseudonymize`] is enabled, `user_id` is pseudonymized under
+/// the *current* rotation month before the lookup, matching how it was stored
+/// at write time. A request made in a later calendar month than the checkins
+/// it's trying to reach will come back empty — an accepted consequence of
+/// unlinkability across months, not a bug.
+pub async fn export_user_checkins(
+    user_id: String,
+    client: AuthenticatedClient,
+    db: Db,
+    pseudonymizer: SharedPseudonymizer,
+) -> Result<impl Reply, Rejection> {
+    let user_id = match &pseudonymizer {
+        Some(pseudonymizer) => pseudonymizer.apply(&user_id),
+        None => user_id,
+    };
+    let rows: Vec<Checkin> = db
+        .run(move |conn| {
+            use crate::schema::checkins::dsl;
+            dsl::checkins
+                .filter(dsl::user_id.eq(user_id))
+                .filter(dsl::client_id.eq(client.client_id))
+                .order(dsl::created_at.asc())
+                .load(conn)
         })
+        .await
+        .map_err(ApiError::from)
+        .map_err(warp::reject::custom)?;
+
+    Ok(warp::reply::json(&rows))
+}
+
+/// GDPR erasure: deletes every checkin a user submitted through the
+/// requesting client, in a single transaction so the count logged always
+/// matches what actually happened.
+///
+/// Unlike [`export_user_checkins`], an unmatched erasure request can't just
+/// come back empty -- a right-to-erasure guarantee can't silently no-op
+/// because the rotation month moved on since the checkin was written. So
+/// this matches against every pseudonym `user_id` could have been stored
+/// under (see [`crate::pseudonymize::Pseudonymizer::candidates`]), not just
+/// the current month's.
+pub async fn erase_user_checkins(
+    user_id: String,
+    request_id: String,
+    client: AuthenticatedClient,
+    db: Db,
+    pseudonymizer: SharedPseudonymizer,
+) -> Result<impl Reply, Rejection> {
+    let user_ids = match &pseudonymizer {
+        Some(pseudonymizer) => pseudonymizer.candidates(&user_id),
+        None => vec![user_id],
+    };
+    let audit_request_id = request_id.clone();
+    let deleted: usize = db
+        .run(move |conn| {
+            conn.transaction(|| {
+                use crate::schema::checkin_tombstones;
+                use crate::schema::checkins::dsl;
+                let removed: Vec<Checkin> = diesel::delete(
+                    dsl::checkins
+                        .filter(dsl::user_id.eq_any(user_ids))
+                        .filter(dsl::client_id.eq(client.client_id)),
+                )
+                .get_results(conn)?;
+
+                let now = chrono::Utc::now().naive_utc();
+                let tombstones: Vec<CheckinTombstone> = removed
+                    .iter()
+                    .map(|checkin| CheckinTombstone {
+                        checkin_id: checkin.id,
+                        deleted_at: now,
+                    })
+                    .collect();
+                if !tombstones.is_empty() {
+                    diesel::insert_into(checkin_tombstones::table)
+                        .values(&tombstones)
+                        .execute(conn)?;
+                }
+
+                for checkin in &removed {
+                    crate::audit::record(
+                        conn,
+                        &checkin.user_id,
+                        "delete",
+                        "DELETE /v1/users/:id",
+                        "checkin",
+                        checkin.id,
+                        checkin,
+                        &audit_request_id,
+                    )?;
+                }
+
+                Ok(removed.len())
+            })
+        })
+        .await
+        .map_err(ApiError::from)
+        .map_err(warp::reject::custom)?;
+
+    info!(request_id = %request_id, deleted, "erased user checkins");
+    Ok(StatusCode::NO_CONTENT)
 }
@@ -0,0 +1,47 @@
+//! Liveness/readiness probes, kept outside the `/v1` prefix since they're
+//! infrastructure concerns rather than API surface.
+
+use crate::db::Db;
+use diesel::sql_types::Integer;
+use diesel::RunQueryDsl;
+use warp::http::StatusCode;
+use warp::{Filter, Rejection, Reply};
+
+pub fn routes(db: Db) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    healthz().or(readyz(db))
+}
+
+fn healthz() -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path!("healthz")
+        .and(warp::get())
+        .map(|| StatusCode::OK)
+}
+
+fn readyz(db: Db) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path!("readyz")
+        .and(warp::get())
+        .and(with_db(db))
+        .and_then(check_ready)
+}
+
+async fn check_ready(db: Db) -> Result<impl Reply, Rejection> {
+    let result = db
+        .run(|conn| diesel::sql_query("SELECT 1").get_result::<SelectOne>(conn))
+        .await;
+
+    match result {
+        Ok(_) => Ok(StatusCode::OK),
+        Err(_) => Ok(StatusCode::SERVICE_UNAVAILABLE),
+    }
+}
+
+#[derive(QueryableByName)]
+struct SelectOne {
+    #[sql_type = "Integer"]
+    #[column_name = "?column?"]
+    _one: i32,
+}
+
+fn with_db(db: Db) -> impl Filter<Extract = (Db,), Error = std::convert::Infallible> + Clone {
+    warp::any().map(move || db.clone())
+}
@@ -0,0 +1,237 @@
+//! Gamification: a contributor leaderboard and per-user achievement badges,
+//! computed from checkin history to encourage reporting. The leaderboard
+//! only ever shows a registered [`crate::model::UserHandle`], never the raw
+//! `user_id` -- contributors who haven't opted in with `register_handle`
+//! simply don't appear, which both keeps identifiers off a public endpoint
+//! (see [`crate::pseudonymize`], [`crate::export`]) and gives people a
+//! concrete reason to register one.
+
+use crate::cache::Cache;
+use crate::db::{Db, DbError};
+use crate::errors::ApiError;
+use crate::model::{MAX_HANDLE_LENGTH, RegisterHandleJson, UserHandle};
+use crate::schema::user_handles;
+use chrono::{Duration, Utc};
+use diesel::dsl::sql_query;
+use diesel::prelude::*;
+use diesel::sql_types::{BigInt, Text, Timestamp};
+use serde::{Deserialize, Serialize};
+use warp::{Rejection, Reply};
+
+#[derive(Debug, Deserialize)]
+pub struct LeaderboardQuery {
+    #[serde(default = "default_period")]
+    pub period: String,
+    #[serde(default = "default_limit")]
+    pub limit: i64,
+}
+
+fn default_period() -> String {
+    "week".to_string()
+}
+
+fn default_limit() -> i64 {
+    20
+}
+
+#[derive(Debug, QueryableByName)]
+struct LeaderboardRow {
+    #[sql_type = "Text"]
+    handle: String,
+    #[sql_type = "BigInt"]
+    checkin_count: i64,
+    #[sql_type = "BigInt"]
+    distinct_locations: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LeaderboardEntry {
+    pub handle: String,
+    pub checkin_count: i64,
+    pub distinct_locations: i64,
+}
+
+pub async fn leaderboard(
+    query: LeaderboardQuery,
+    cache: Cache,
+    db: Db,
+) -> Result<impl Reply, Rejection> {
+    let period = match query.period.as_str() {
+        "day" | "month" | "all" => query.period.as_str(),
+        _ => "week",
+    };
+    let limit = query.limit.clamp(1, 100);
+
+    let cache_key = format!("leaderboard:{}:{}", period, limit);
+    if let Some(cached) = cache.get(&cache_key) {
+        return Ok(warp::reply::with_header(
+            cached,
+            "Content-Type",
+            "application/json",
+        ));
+    }
+
+    let since = match period {
+        "day" => Utc::now().naive_utc() - Duration::days(1),
+        "month" => Utc::now().naive_utc() - Duration::days(30),
+        "all" => chrono::NaiveDate::from_ymd(1970, 1, 1).and_hms(0, 0, 0),
+        _ => Utc::now().naive_utc() - Duration::days(7), // "week"
+    };
+
+    let rows: Vec<LeaderboardRow> = db
+        .run(move |conn| {
+            sql_query(
+                "select h.handle as handle, count(*) as checkin_count, \
+                 count(distinct c.location_id) as distinct_locations \
+                 from checkins c \
+                 join user_handles h on h.user_id = c.user_id \
+                 where c.created_at >= $1 and not c.hidden \
+                 group by h.handle \
+                 order by checkin_count desc \
+                 limit $2",
+            )
+            .bind::<Timestamp, _>(since)
+            .bind::<BigInt, _>(limit)
+            .get_results(conn)
+        })
+        .await
+        .map_err(ApiError::from)
+        .map_err(warp::reject::custom)?;
+
+    let entries: Vec<LeaderboardEntry> = rows
+        .into_iter()
+        .map(|row| LeaderboardEntry {
+            handle: row.handle,
+            checkin_count: row.checkin_count,
+            distinct_locations: row.distinct_locations,
+        })
+        .collect();
+
+    let body = serde_json::to_string(&entries).expect("serialize leaderboard entries");
+    cache.put(cache_key, body.clone());
+
+    Ok(warp::reply::with_header(
+        body,
+        "Content-Type",
+        "application/json",
+    ))
+}
+
+pub async fn register_handle(
+    user_id: String,
+    body: RegisterHandleJson,
+    db: Db,
+) -> Result<impl Reply, Rejection> {
+    let handle = body.handle.trim().to_string();
+    if handle.is_empty() {
+        return Err(warp::reject::custom(ApiError::Validation(
+            "handle must not be empty".to_string(),
+        )));
+    }
+    if handle.chars().count() > MAX_HANDLE_LENGTH {
+        return Err(warp::reject::custom(ApiError::Validation(format!(
+            "handle must not exceed {} characters",
+            MAX_HANDLE_LENGTH
+        ))));
+    }
+
+    let saved: UserHandle = db
+        .run(move |conn| {
+            diesel::insert_into(user_handles::table)
+                .values((
+                    user_handles::user_id.eq(&user_id),
+                    user_handles::handle.eq(&handle),
+                ))
+                .on_conflict(user_handles::user_id)
+                .do_update()
+                .set(user_handles::handle.eq(&handle))
+                .get_result(conn)
+        })
+        .await
+        .map_err(|e| match e {
+            DbError::Query(diesel::result::Error::DatabaseError(
+                diesel::result::DatabaseErrorKind::UniqueViolation,
+                _,
+            )) => ApiError::Conflict("handle already taken".to_string()),
+            other => ApiError::from(other),
+        })
+        .map_err(warp::reject::custom)?;
+
+    Ok(warp::reply::json(&saved))
+}
+
+/// Checkin-count and distinct-location thresholds that unlock a badge.
+/// `(threshold, code, title)`.
+const CHECKIN_MILESTONES: &[(i64, &str, &str)] = &[
+    (1, "first_report", "Made your first report"),
+    (10, "regular_reporter", "Reported 10 times"),
+    (50, "community_pillar", "Reported 50 times"),
+    (100, "super_reporter", "Reported 100 times"),
+];
+
+const LOCATION_MILESTONES: &[(i64, &str, &str)] = &[
+    (5, "explorer", "Reported at 5 different locations"),
+    (20, "cartographer", "Reported at 20 different locations"),
+];
+
+#[derive(Debug, Serialize)]
+pub struct Achievement {
+    pub code: &'static str,
+    pub title: &'static str,
+    pub earned: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AchievementsResponse {
+    pub checkin_count: i64,
+    pub distinct_locations: i64,
+    pub achievements: Vec<Achievement>,
+}
+
+pub async fn achievements(user_id: String, db: Db) -> Result<impl Reply, Rejection> {
+    let (checkin_count, distinct_locations) = db
+        .run(move |conn| {
+            use crate::schema::checkins::dsl;
+
+            let checkin_count: i64 = dsl::checkins
+                .filter(dsl::user_id.eq(&user_id))
+                .count()
+                .get_result(conn)?;
+            let distinct_locations = dsl::checkins
+                .filter(dsl::user_id.eq(&user_id))
+                .filter(dsl::location_id.is_not_null())
+                .select(dsl::location_id)
+                .distinct()
+                .load::<Option<i32>>(conn)?
+                .len() as i64;
+
+            Ok((checkin_count, distinct_locations))
+        })
+        .await
+        .map_err(ApiError::from)
+        .map_err(warp::reject::custom)?;
+
+    let achievements: Vec<Achievement> = CHECKIN_MILESTONES
+        .iter()
+        .map(|(threshold, code, title)| Achievement {
+            code,
+            title,
+            earned: checkin_count >= *threshold,
+        })
+        .chain(
+            LOCATION_MILESTONES
+                .iter()
+                .map(|(threshold, code, title)| Achievement {
+                    code,
+                    title,
+                    earned: distinct_locations >= *threshold,
+                }),
+        )
+        .collect();
+
+    Ok(warp::reply::json(&AchievementsResponse {
+        checkin_count,
+        distinct_locations,
+        achievements,
+    }))
+}
@@ -0,0 +1,119 @@
+//! The `locations` resource and the fuzzy name+proximity matching used to
+//! resolve a checkin's free-text `location_name` to a deduplicated place, so
+//! "REWE Hauptstraße" and "Rewe Hauptstr." end up in the same stats bucket.
+
+use crate::db::Db;
+use crate::errors::ApiError;
+use crate::model::{Location, NewLocation, NewLocationJson};
+use crate::schema::locations;
+use diesel::dsl::sql_query;
+use diesel::prelude::*;
+use diesel::sql_types::{Int4, Text};
+use diesel_geography::types::GeogPoint;
+use serde::Deserialize;
+use warp::{Rejection, Reply};
+
+/// Locations within this radius of an incoming checkin are considered the
+/// same place, provided the name also matches (case-insensitively).
+const MATCH_RADIUS_METERS: i32 = 75;
+
+/// Finds an existing location with a matching name within `MATCH_RADIUS_METERS`
+/// of `gps`, or inserts a new one, returning its id either way.
+pub fn resolve_or_create(conn: &PgConnection, name: &str, gps: GeogPoint) -> QueryResult<i32> {
+    let q = format!(
+        "select * from locations where lower(name) = lower($1) \
+         and ST_DWithin(gps, 'point({} {})', $2, false) limit 1",
+        gps.x, gps.y
+    );
+    let existing = sql_query(q)
+        .bind::<Text, _>(name)
+        .bind::<Int4, _>(MATCH_RADIUS_METERS)
+        .get_result::<Location>(conn)
+        .optional()?;
+
+    if let Some(location) = existing {
+        return Ok(location.id);
+    }
+
+    let inserted: Location = diesel::insert_into(locations::table)
+        .values(NewLocation {
+            name: name.to_string(),
+            gps,
+            category: None,
+            opening_hours: None,
+        })
+        .get_result(conn)?;
+    Ok(inserted.id)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ListLocationsQuery {
+    /// When present, only returns locations whose `opening_hours` can be
+    /// parsed and definitively says open (`true`) or closed (`false`) right
+    /// now. Locations with no or unparseable `opening_hours` are excluded
+    /// either way, since their state can't be confirmed.
+    pub open_now: Option<bool>,
+}
+
+/// Whether `location_id` looks open at `at`, per its `opening_hours` (see
+/// [`crate::opening_hours`]). `Ok(None)` covers both "no such location" and
+/// "hours unknown/unparseable" — callers should treat it as "can't tell",
+/// not "closed".
+pub fn is_open_at(
+    conn: &PgConnection,
+    location_id: i32,
+    at: chrono::NaiveDateTime,
+) -> QueryResult<Option<bool>> {
+    let opening_hours: Option<String> = locations::table
+        .find(location_id)
+        .select(locations::opening_hours)
+        .first(conn)
+        .optional()?
+        .flatten();
+    Ok(opening_hours.and_then(|spec| crate::opening_hours::is_open_at(&spec, at)))
+}
+
+pub async fn list_locations(query: ListLocationsQuery, db: Db) -> Result<impl Reply, Rejection> {
+    let results: Vec<Location> = db
+        .run(|conn| locations::table.order(locations::id.asc()).load(conn))
+        .await
+        .map_err(ApiError::from)
+        .map_err(warp::reject::custom)?;
+
+    let results = match query.open_now {
+        Some(want_open) => {
+            let now = chrono::Utc::now().naive_utc();
+            results
+                .into_iter()
+                .filter(|location| {
+                    location
+                        .opening_hours
+                        .as_deref()
+                        .and_then(|spec| crate::opening_hours::is_open_at(spec, now))
+                        == Some(want_open)
+                })
+                .collect()
+        }
+        None => results,
+    };
+
+    Ok(warp::reply::json(&results))
+}
+
+pub async fn create_location(
+    location: NewLocationJson,
+    db: Db,
+) -> Result<impl Reply, Rejection> {
+    let new_location = NewLocation::from(location);
+    let inserted: Location = db
+        .run(move |conn| {
+            diesel::insert_into(locations::table)
+                .values(new_location)
+                .get_result(conn)
+        })
+        .await
+        .map_err(ApiError::from)
+        .map_err(warp::reject::custom)?;
+
+    Ok(warp::reply::json(&inserted))
+}
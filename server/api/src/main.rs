@@ -1,98 +1,421 @@
 #[macro_use]
 extern crate diesel;
 #[macro_use]
-extern crate log;
+extern crate diesel_migrations;
+#[macro_use]
+extern crate tracing;
+
+embed_migrations!("migrations");
 
+mod admin;
+mod analytics;
+mod audit;
+mod auth;
+mod backend;
+mod blobs;
+mod cache;
+mod caching;
+mod cli;
+mod compression;
+mod config;
+mod db;
+mod errors;
+mod export;
 mod filters;
+mod fixtures;
+mod forecast;
+mod geocoding;
+mod geojson;
+mod goods;
 mod handlers;
+mod health;
+mod leaderboard;
+mod locations;
+mod metrics;
 mod model;
+mod openapi;
+mod opening_hours;
+mod photos;
+mod pseudonymize;
+mod push;
+mod rate_limit;
+mod request_id;
+mod reputation;
+mod retention;
 mod schema;
+mod stats;
+mod status;
+mod storage;
+mod streaming;
+mod sync;
+#[cfg(test)]
+mod test_support;
+mod v2;
+mod watches;
+mod webhooks;
+mod ws;
 
 use diesel::r2d2;
 use diesel::PgConnection;
 use dotenv::dotenv;
 use std::env;
+use std::sync::Arc;
+use std::time::Duration;
 use warp::Filter;
 
 type Pool = r2d2::Pool<r2d2::ConnectionManager<PgConnection>>;
 
-fn get_connection_pool() -> Pool {
+/// Builds the connection pool, retrying with a fixed delay if Postgres isn't
+/// reachable yet rather than panicking on the first attempt -- container
+/// orchestrators routinely start this service before its database is ready
+/// to accept connections.
+fn get_connection_pool(config: &config::Config) -> Pool {
     dotenv().ok();
     let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
     let cm = r2d2::ConnectionManager::<PgConnection>::new(database_url);
-    let pool = r2d2::Pool::builder()
-        .max_size(15)
-        .build(cm)
-        .expect("build connection pool");
-    pool
+
+    let mut attempt = 0;
+    loop {
+        let result = r2d2::Pool::builder()
+            .max_size(config.database_pool_size)
+            .min_idle(config.database_min_idle)
+            .connection_timeout(Duration::from_secs(config.database_connection_timeout_secs))
+            .build(cm.clone());
+
+        match result {
+            Ok(pool) => return pool,
+            Err(e) if attempt < config.database_connect_retries => {
+                attempt += 1;
+                warn!(
+                    attempt,
+                    retries_left = config.database_connect_retries - attempt,
+                    error = %e,
+                    "database not reachable yet, retrying in {}s",
+                    config.database_connect_retry_delay_secs
+                );
+                std::thread::sleep(Duration::from_secs(config.database_connect_retry_delay_secs));
+            }
+            Err(e) => panic!(
+                "build connection pool after {} attempts: {}",
+                attempt + 1,
+                e
+            ),
+        }
+    }
 }
 
 #[tokio::main]
 async fn main() {
     if env::var_os("RUST_LOG").is_none() {
-        // Set `RUST_LOG=todos=debug` to see debug logs,
+        // Set `RUST_LOG=checkins=debug` to see debug logs,
         // this only shows access logs.
         env::set_var("RUST_LOG", "checkins=trace");
     }
 
-    pretty_env_logger::init();
-    let pool = get_connection_pool();
-    let api = filters::checkins(pool);
+    tracing_subscriber::fmt()
+        .json()
+        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
+        .init();
+
+    let config = config::Config::from_env();
+
+    match cli::parse() {
+        cli::Command::SeedDemo => {
+            let pool = get_connection_pool(&config);
+            let conn = pool.get().expect("get connection for seeding");
+            let inserted = fixtures::seed(&conn).expect("seed demo data");
+            info!("seeded {} demo checkins", inserted);
+            return;
+        }
+        cli::Command::PseudonymizeMigrate => {
+            let secret = config
+                .pseudonymization_secret
+                .clone()
+                .expect("PSEUDONYMIZATION_SECRET must be set to run pseudonymize-migrate");
+            let pseudonymizer = pseudonymize::Pseudonymizer::new(secret);
+            let pool = get_connection_pool(&config);
+            let conn = pool.get().expect("get connection for migration");
+            let migrated = pseudonymize::migrate_existing_rows(&conn, &pseudonymizer)
+                .expect("pseudonymize existing rows");
+            info!("pseudonymized {} existing rows", migrated);
+            return;
+        }
+        cli::Command::Migrate => {
+            let pool = get_connection_pool(&config);
+            let conn = pool.get().expect("get connection for migrations");
+            info!("running pending migrations");
+            embedded_migrations::run_with_output(&conn, &mut std::io::stdout())
+                .expect("run embedded migrations");
+            return;
+        }
+        cli::Command::Purge { older_than_days } => {
+            let pool = get_connection_pool(&config);
+            let conn = pool.get().expect("get connection for purge");
+            let purged =
+                retention::purge_older_than(&conn, older_than_days).expect("purge old checkins");
+            info!(purged, older_than_days, "purged checkins via CLI");
+            return;
+        }
+        cli::Command::Export { format, from, to } => {
+            let pool = get_connection_pool(&config);
+            let conn = pool.get().expect("get connection for export");
+            export::export_to_stdout(&conn, &format, from, to).expect("export checkins");
+            return;
+        }
+        cli::Command::CreateApiKey { client_id } => {
+            let pool = get_connection_pool(&config);
+            let conn = pool.get().expect("get connection for create-api-key");
+            let client = auth::create_api_key(&conn, &client_id).expect("create api key");
+            println!("{}", client.api_key);
+            return;
+        }
+        cli::Command::Serve => {}
+    }
+
+    let pool = get_connection_pool(&config);
+
+    if env::var("RUN_MIGRATIONS").as_deref() == Ok("1") {
+        let conn = pool.get().expect("get connection for migrations");
+        info!("running pending migrations");
+        embedded_migrations::run_with_output(&conn, &mut std::io::stdout())
+            .expect("run embedded migrations");
+    }
+
+    let db = db::Db::new(pool);
+
+    if let Some(retention_days) = config.retention_days {
+        retention::spawn(db.clone(), retention_days);
+    }
+    webhooks::spawn(db.clone());
+
+    let ws_registry = ws::Registry::new();
+    let cache = cache::Cache::new(config.aggregation_cache_ttl_secs);
+    let geocoder: geocoding::SharedGeocoder = config
+        .nominatim_base_url
+        .clone()
+        .map(|base_url| Arc::new(geocoding::NominatimGeocoder::new(base_url)) as Arc<dyn geocoding::Geocoder>);
+    let push_provider: push::SharedPushProvider = config
+        .fcm_server_key
+        .clone()
+        .map(|server_key| Arc::new(push::FcmPushProvider::new(server_key)) as Arc<dyn push::PushProvider>);
+    let pseudonymizer: pseudonymize::SharedPseudonymizer = config
+        .pseudonymization_secret
+        .clone()
+        .map(|secret| Arc::new(pseudonymize::Pseudonymizer::new(secret)));
+    let blob_store: blobs::SharedBlobStore = match &config.s3_bucket {
+        Some(bucket) => {
+            let region = config.s3_region.parse().unwrap_or(rusoto_core::Region::UsEast1);
+            Arc::new(blobs::S3BlobStore::new(bucket.clone(), region)) as Arc<dyn blobs::BlobStore>
+        }
+        None => Arc::new(blobs::FsBlobStore::new(config.photo_storage_dir.clone())) as Arc<dyn blobs::BlobStore>,
+    };
+    let api = health::routes(db.clone())
+        .or(metrics::routes(db.clone()))
+        .or(ws::routes(ws_registry.clone()))
+        .or(admin::routes(db.clone(), cache.clone()))
+        .or(openapi::routes())
+        .or(filters::checkins_v2(
+            db.clone(),
+            config.body_limit_bytes,
+            config.checkin_rate_limit_per_minute,
+            ws_registry.clone(),
+            cache.clone(),
+            pseudonymizer.clone(),
+        ))
+        .or(filters::checkins(
+            db,
+            config.body_limit_bytes,
+            config.checkin_rate_limit_per_minute,
+            ws_registry,
+            cache,
+            geocoder,
+            push_provider,
+            blob_store,
+            config.photo_limit_bytes,
+            pseudonymizer,
+        ));
     let cors = warp::cors()
-        .allow_origin("http://localhost:5000")
+        .allow_origins(config.allowed_origins.iter().map(String::as_str))
         .allow_header("Content-Type")
+        .allow_header("Authorization")
         .allow_methods(vec!["GET", "POST", "PUT", "DELETE"]);
-    let routes = api.with(warp::log("checkins")).with(cors);
+    let routes = api
+        .recover(errors::recover)
+        .with(warp::log::custom(metrics::record_request));
+    let routes = request_id::with_header(routes);
+    let routes = compression::with_gzip(routes, config.gzip_enabled).with(cors);
+
+    if let (Some(cert_path), Some(key_path)) = (&config.tls_cert_path, &config.tls_key_path) {
+        // warp bakes the TLS acceptor into the listener at bind time, so
+        // there's no hot-swap hook for a renewed certificate; reloading on
+        // SIGHUP means gracefully draining the current listener and binding
+        // a fresh one that re-reads the files.
+        loop {
+            let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+            let (addr, server) = warp::serve(routes.clone())
+                .tls()
+                .cert_path(cert_path)
+                .key_path(key_path)
+                .bind_with_graceful_shutdown((config.bind_addr, config.port), async {
+                    rx.await.ok();
+                });
+            info!("listening on https://{}", addr);
+            let server_handle = tokio::task::spawn(server);
+
+            tokio::select! {
+                _ = shutdown_signal() => {
+                    let _ = tx.send(());
+                    drain(server_handle, config.shutdown_timeout_secs).await;
+                    break;
+                }
+                _ = reload_signal() => {
+                    info!("SIGHUP received, reloading TLS certificate");
+                    let _ = tx.send(());
+                    drain(server_handle, config.shutdown_timeout_secs).await;
+                }
+            }
+        }
+    } else {
+        let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+        let (addr, server) = warp::serve(routes).bind_with_graceful_shutdown(
+            (config.bind_addr, config.port),
+            async {
+                rx.await.ok();
+            },
+        );
+        info!("starting server on {}", addr);
+        let server_handle = tokio::task::spawn(server);
 
-    info!("starting server");
-    warp::serve(routes).run(([127, 0, 0, 1], 3000)).await;
+        shutdown_signal().await;
+        let _ = tx.send(());
+        drain(server_handle, config.shutdown_timeout_secs).await;
+    }
+    // `pool` (and every `Db` clone holding it) is dropped here, closing the
+    // r2d2 connections along with the rest of the process state.
+}
+
+/// Waits for `handle` to finish draining in-flight requests, giving up after
+/// `timeout_secs` so a stuck connection can't block shutdown forever.
+async fn drain(handle: tokio::task::JoinHandle<()>, timeout_secs: u64) {
+    let timeout = std::time::Duration::from_secs(timeout_secs);
+    info!("draining in-flight requests (timeout {:?})", timeout);
+    match tokio::time::timeout(timeout, handle).await {
+        Ok(_) => info!("server shut down cleanly"),
+        Err(_) => warn!("graceful shutdown timed out, exiting anyway"),
+    }
+}
+
+/// Resolves once a SIGTERM or Ctrl-C is received, so `main` can stop
+/// accepting new connections and start draining in-flight ones.
+async fn shutdown_signal() {
+    let ctrl_c = tokio::signal::ctrl_c();
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("install SIGTERM handler")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+}
+
+/// Resolves once a SIGHUP is received, signalling a TLS certificate reload.
+/// Never resolves on non-Unix targets.
+async fn reload_signal() {
+    #[cfg(unix)]
+    {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())
+            .expect("install SIGHUP handler")
+            .recv()
+            .await;
+    }
+    #[cfg(not(unix))]
+    {
+        std::future::pending::<()>().await;
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::get_connection_pool;
     use crate::model::CheckinsAroundRequest;
-    use crate::model::NewJsonCheckin;
+    use crate::test_support::{api, checkin_json, test_pool, TEST_API_KEY};
     use warp::http::StatusCode;
     use warp::test::request;
 
-    use super::filters;
+    #[tokio::test]
+    async fn test_checkin() {
+        let api = api(test_pool());
 
-    fn make_checkin() -> NewJsonCheckin {
-        NewJsonCheckin {
-            gps: [53.55, 9.97],
-            location_name: "some location".to_string(),
-            crowded_level: 3,
-            user_id: "some user".to_string(),
-            client_id: "some client".to_string(),
-            missing_goods: vec![String::from("flour")],
-        }
+        let res = request()
+            .method("POST")
+            .path("/v1/checkins")
+            .header("authorization", format!("Bearer {}", TEST_API_KEY))
+            .json(&checkin_json())
+            .reply(&api)
+            .await;
+
+        assert_eq!(res.status(), StatusCode::CREATED);
     }
 
     #[tokio::test]
-    async fn test_checkin() {
-        let db = get_connection_pool();
-        let api = filters::checkins(db);
+    async fn test_checkin_rejects_unknown_api_key() {
+        let api = api(test_pool());
 
         let res = request()
             .method("POST")
             .path("/v1/checkins")
-            .json(&make_checkin())
+            .header("authorization", "Bearer not-a-real-key")
+            .json(&checkin_json())
             .reply(&api)
             .await;
 
+        assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[tokio::test]
+    async fn test_checkin_idempotency_key_replay() {
+        let api = api(test_pool());
+
+        let res = request()
+            .method("POST")
+            .path("/v1/checkins")
+            .header("authorization", format!("Bearer {}", TEST_API_KEY))
+            .header("idempotency-key", "retry-test-key")
+            .json(&checkin_json())
+            .reply(&api)
+            .await;
         assert_eq!(res.status(), StatusCode::CREATED);
+        let first: serde_json::Value = serde_json::from_slice(res.body()).unwrap();
+
+        let res = request()
+            .method("POST")
+            .path("/v1/checkins")
+            .header("authorization", format!("Bearer {}", TEST_API_KEY))
+            .header("idempotency-key", "retry-test-key")
+            .json(&checkin_json())
+            .reply(&api)
+            .await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let replayed: serde_json::Value = serde_json::from_slice(res.body()).unwrap();
+        assert_eq!(first["id"], replayed["id"]);
     }
 
     #[tokio::test]
     async fn test_checkins_around() {
-        let db = get_connection_pool();
-        let api = filters::checkins(db);
+        let api = api(test_pool());
 
         let res = request()
             .method("POST")
             .path("/v1/checkins")
-            .json(&make_checkin())
+            .header("authorization", format!("Bearer {}", TEST_API_KEY))
+            .json(&checkin_json())
             .reply(&api)
             .await;
 
@@ -114,4 +437,117 @@ mod tests {
 
         assert_eq!(res.status(), StatusCode::OK);
     }
+
+    #[tokio::test]
+    async fn test_checkins_geojson_streams_valid_feature_collection() {
+        let api = api(test_pool());
+
+        let res = request()
+            .method("POST")
+            .path("/v1/checkins")
+            .header("authorization", format!("Bearer {}", TEST_API_KEY))
+            .json(&checkin_json())
+            .reply(&api)
+            .await;
+        assert_eq!(res.status(), StatusCode::CREATED);
+
+        let res = request()
+            .method("GET")
+            .path("/v1/checkins/geojson")
+            .reply(&api)
+            .await;
+
+        assert_eq!(res.status(), StatusCode::OK);
+        let parsed: serde_json::Value = serde_json::from_slice(res.body()).unwrap();
+        assert_eq!(parsed["type"], "FeatureCollection");
+        assert!(parsed["features"].as_array().unwrap().len() >= 1);
+    }
+
+    #[tokio::test]
+    async fn test_checkins_list() {
+        let api = api(test_pool());
+
+        let res = request()
+            .method("POST")
+            .path("/v1/checkins")
+            .header("authorization", format!("Bearer {}", TEST_API_KEY))
+            .json(&checkin_json())
+            .reply(&api)
+            .await;
+        assert_eq!(res.status(), StatusCode::CREATED);
+
+        let res = request()
+            .method("GET")
+            .path("/v1/checkins")
+            .reply(&api)
+            .await;
+
+        assert_eq!(res.status(), StatusCode::OK);
+        let page: serde_json::Value = serde_json::from_slice(res.body()).unwrap();
+        assert!(page["total"].as_i64().unwrap() >= 1);
+        assert!(!page["items"].as_array().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_checkins_nearby() {
+        let api = api(test_pool());
+
+        let res = request()
+            .method("POST")
+            .path("/v1/checkins")
+            .header("authorization", format!("Bearer {}", TEST_API_KEY))
+            .json(&checkin_json())
+            .reply(&api)
+            .await;
+        assert_eq!(res.status(), StatusCode::CREATED);
+
+        let res = request()
+            .method("GET")
+            .path("/v1/checkins/nearby?lat=53.55&lng=9.97&radius=1000")
+            .reply(&api)
+            .await;
+
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_user_export_and_erase() {
+        let api = api(test_pool());
+
+        let res = request()
+            .method("POST")
+            .path("/v1/checkins")
+            .header("authorization", format!("Bearer {}", TEST_API_KEY))
+            .json(&checkin_json())
+            .reply(&api)
+            .await;
+        assert_eq!(res.status(), StatusCode::CREATED);
+
+        let res = request()
+            .method("GET")
+            .path("/v1/users/some%20user/export")
+            .header("authorization", format!("Bearer {}", TEST_API_KEY))
+            .reply(&api)
+            .await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let exported: Vec<serde_json::Value> = serde_json::from_slice(res.body()).unwrap();
+        assert!(!exported.is_empty());
+
+        let res = request()
+            .method("DELETE")
+            .path("/v1/users/some%20user")
+            .header("authorization", format!("Bearer {}", TEST_API_KEY))
+            .reply(&api)
+            .await;
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+
+        let res = request()
+            .method("GET")
+            .path("/v1/users/some%20user/export")
+            .header("authorization", format!("Bearer {}", TEST_API_KEY))
+            .reply(&api)
+            .await;
+        let exported: Vec<serde_json::Value> = serde_json::from_slice(res.body()).unwrap();
+        assert!(exported.is_empty());
+    }
 }
@@ -0,0 +1,81 @@
+//! Prometheus instrumentation. Counters and a latency histogram are updated
+//! from a `warp::log::custom` hook in `main.rs`; pool gauges are refreshed
+//! whenever `/metrics` is scraped, since they only matter at read time.
+
+use crate::db::Db;
+use lazy_static::lazy_static;
+use prometheus::{Encoder, HistogramVec, IntCounter, IntCounterVec, IntGaugeVec, TextEncoder};
+use warp::{Filter, Rejection, Reply};
+
+lazy_static! {
+    static ref REQUESTS_TOTAL: IntCounterVec = prometheus::register_int_counter_vec!(
+        "checkins_requests_total",
+        "Total HTTP requests handled, by route and status code.",
+        &["route", "status"]
+    )
+    .unwrap();
+    static ref REQUEST_DURATION_SECONDS: HistogramVec = prometheus::register_histogram_vec!(
+        "checkins_request_duration_seconds",
+        "HTTP request latency in seconds, by route.",
+        &["route"]
+    )
+    .unwrap();
+    static ref POOL_CONNECTIONS: IntGaugeVec = prometheus::register_int_gauge_vec!(
+        "checkins_db_pool_connections",
+        "Connections currently held by the r2d2 pool, by state.",
+        &["state"]
+    )
+    .unwrap();
+    static ref RETENTION_PURGED_TOTAL: IntCounter = prometheus::register_int_counter!(
+        "checkins_retention_purged_total",
+        "Total checkins deleted by the data retention job."
+    )
+    .unwrap();
+}
+
+/// Called from `warp::log::custom` for every request.
+pub fn record_request(info: warp::log::Info) {
+    let route = info.path();
+    let status = info.status().as_u16().to_string();
+    REQUESTS_TOTAL.with_label_values(&[route, &status]).inc();
+    REQUEST_DURATION_SECONDS
+        .with_label_values(&[route])
+        .observe(info.elapsed().as_secs_f64());
+}
+
+/// Called by the retention job after each purge run.
+pub fn record_retention_purge(purged: usize) {
+    RETENTION_PURGED_TOTAL.inc_by(purged as u64);
+}
+
+pub fn routes(db: Db) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path!("metrics")
+        .and(warp::get())
+        .and(with_db(db))
+        .and_then(serve_metrics)
+}
+
+async fn serve_metrics(db: Db) -> Result<impl Reply, Rejection> {
+    let state = db.pool_state();
+    POOL_CONNECTIONS
+        .with_label_values(&["idle"])
+        .set(i64::from(state.idle_connections));
+    POOL_CONNECTIONS
+        .with_label_values(&["active"])
+        .set(i64::from(state.connections - state.idle_connections));
+
+    let metric_families = prometheus::gather();
+    let encoder = TextEncoder::new();
+    let mut buffer = Vec::new();
+    encoder.encode(&metric_families, &mut buffer).unwrap();
+
+    Ok(warp::reply::with_header(
+        buffer,
+        "Content-Type",
+        encoder.format_type(),
+    ))
+}
+
+fn with_db(db: Db) -> impl Filter<Extract = (Db,), Error = std::convert::Infallible> + Clone {
+    warp::any().map(move || db.clone())
+}
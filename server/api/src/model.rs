@@ -1,20 +1,120 @@
+use crate::schema::banned_clients;
+use crate::schema::checkin_goods;
+use crate::schema::checkin_photos;
+use crate::schema::checkin_tombstones;
 use crate::schema::checkins;
+use crate::schema::clients;
+use crate::schema::goods;
+use crate::schema::locations;
+use crate::schema::user_handles;
+use crate::schema::watches;
+use crate::schema::webhooks;
+use chrono::DateTime;
+use chrono::Duration;
 use chrono::NaiveDateTime;
 use chrono::Utc;
 use diesel_geography::types::GeogPoint;
 use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
 pub struct NewJsonCheckin {
+    /// `[lat, lng]`.
     pub gps: [f64; 2],
     pub location_name: String,
     pub crowded_level: i32,
     pub user_id: String,
     pub client_id: String,
     pub missing_goods: Vec<String>,
+    /// Free-text note, e.g. "only the 1kg bags were gone". Optional so `/v1`
+    /// clients that predate it keep working unchanged.
+    #[serde(default)]
+    pub note: Option<String>,
+    /// When the client actually saw the crowding, for offline clients that
+    /// sync minutes or hours late. Falls back to insert time when absent.
+    #[serde(default)]
+    pub observed_at: Option<DateTime<Utc>>,
 }
 
-#[derive(Debug, Insertable, Serialize, Deserialize)]
+/// How far in the past `observed_at` may point. Catches clients replaying
+/// stale cached payloads rather than genuine offline backfill.
+pub const MAX_OBSERVED_AT_AGE_DAYS: i64 = 30;
+
+/// Caps on `missing_goods`, shared by [`NewJsonCheckin::validate`] and
+/// `handlers::update_checkin` -- keeps one request from ballooning past the
+/// body size limit with a single absurdly large array or string instead of
+/// many small requests.
+pub const MAX_MISSING_GOODS: usize = 20;
+pub const MAX_MISSING_GOOD_LENGTH: usize = 100;
+
+/// Field-level checks for `missing_goods`, shared between
+/// [`NewJsonCheckin::validate`] and `handlers::update_checkin` so both the
+/// create and edit paths enforce the same limits.
+pub fn validate_missing_goods(missing_goods: &[String]) -> Vec<String> {
+    let mut errors = Vec::new();
+    if missing_goods.len() > MAX_MISSING_GOODS {
+        errors.push(format!(
+            "missing_goods must not have more than {} items",
+            MAX_MISSING_GOODS
+        ));
+    }
+    if missing_goods.iter().any(|good| good.chars().count() > MAX_MISSING_GOOD_LENGTH) {
+        errors.push(format!(
+            "each missing_goods entry must not exceed {} characters",
+            MAX_MISSING_GOOD_LENGTH
+        ));
+    }
+    errors
+}
+
+impl NewJsonCheckin {
+    /// Field-level checks that don't require a database round trip. Returns
+    /// one message per invalid field so the client can point the user at it.
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut errors = Vec::new();
+        let [lat, lng] = self.gps;
+
+        if !(-90.0..=90.0).contains(&lat) {
+            errors.push("gps[0] (lat) must be between -90 and 90".to_string());
+        }
+        if !(-180.0..=180.0).contains(&lng) {
+            errors.push("gps[1] (lng) must be between -180 and 180".to_string());
+        }
+        if self.location_name.trim().is_empty() {
+            errors.push("location_name must not be empty".to_string());
+        }
+        if !(0..=5).contains(&self.crowded_level) {
+            errors.push("crowded_level must be between 0 and 5".to_string());
+        }
+        if self.user_id.trim().is_empty() {
+            errors.push("user_id must not be empty".to_string());
+        }
+        if self.client_id.trim().is_empty() {
+            errors.push("client_id must not be empty".to_string());
+        }
+        errors.extend(validate_missing_goods(&self.missing_goods));
+        if let Some(observed_at) = self.observed_at {
+            let now = Utc::now();
+            if observed_at > now {
+                errors.push("observed_at must not be in the future".to_string());
+            } else if now - observed_at > Duration::days(MAX_OBSERVED_AT_AGE_DAYS) {
+                errors.push(format!(
+                    "observed_at must be within the last {} days",
+                    MAX_OBSERVED_AT_AGE_DAYS
+                ));
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Insertable, Serialize, Deserialize)]
 #[table_name = "checkins"]
 pub struct NewCheckin {
     pub gps: GeogPoint,
@@ -24,10 +124,22 @@ pub struct NewCheckin {
     pub user_id: String,
     pub client_id: String,
     pub created_at: NaiveDateTime,
+    pub location_id: Option<i32>,
+    pub updated_at: NaiveDateTime,
+    pub hidden: bool,
+    pub note: Option<String>,
+    pub observed_at: NaiveDateTime,
+    pub idempotency_key: Option<String>,
+    /// Structured address resolved from `gps` by the geocoding subsystem
+    /// (see [`crate::geocoding`]). Always `None` at insert time — filled in
+    /// by a background task shortly after, so absence just means "not
+    /// enriched yet" rather than "lookup failed".
+    pub geocoded_address: Option<String>,
 }
 
 impl From<NewJsonCheckin> for NewCheckin {
     fn from(checkin: NewJsonCheckin) -> NewCheckin {
+        let now = Utc::now().naive_utc();
         NewCheckin {
             gps: GeogPoint {
                 x: checkin.gps[0],
@@ -39,7 +151,14 @@ impl From<NewJsonCheckin> for NewCheckin {
             missing_goods: checkin.missing_goods,
             user_id: checkin.user_id,
             client_id: checkin.client_id,
-            created_at: Utc::now().naive_utc(),
+            created_at: now,
+            location_id: None,
+            updated_at: now,
+            hidden: false,
+            note: checkin.note,
+            observed_at: checkin.observed_at.map(|d| d.naive_utc()).unwrap_or(now),
+            idempotency_key: None,
+            geocoded_address: None,
         }
     }
 }
@@ -55,12 +174,467 @@ pub struct Checkin {
     pub user_id: String,
     pub client_id: String,
     pub created_at: NaiveDateTime,
+    pub location_id: Option<i32>,
+    pub updated_at: NaiveDateTime,
+    pub hidden: bool,
+    pub note: Option<String>,
+    pub observed_at: NaiveDateTime,
+    pub idempotency_key: Option<String>,
+    pub geocoded_address: Option<String>,
+    /// Geohash-prefix shard key, computed by Postgres on insert (see
+    /// [`crate::storage`]). Never set by application code.
+    pub region: String,
+}
+
+/// Body for `PUT /v1/checkins/:id`. Only the original `user_id` may edit a
+/// checkin, and only within `UPDATE_WINDOW_MINUTES` of creation.
+/// `expected_updated_at` is the optimistic-concurrency token: it must match
+/// the row's current `updated_at` or the update is rejected with a 409.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct UpdateCheckinJson {
+    pub user_id: String,
+    pub crowded_level: Option<i32>,
+    pub missing_goods: Option<Vec<String>>,
+    pub expected_updated_at: NaiveDateTime,
+}
+
+/// How long after creation a checkin may still be edited via `PUT`.
+pub const UPDATE_WINDOW_MINUTES: i64 = 15;
+
+/// A deduplicated place, resolved by name + proximity so "REWE
+/// Hauptstraße" and "Rewe Hauptstr." aggregate into the same stats bucket.
+#[derive(Debug, Queryable, QueryableByName, Serialize, Deserialize)]
+#[table_name = "locations"]
+pub struct Location {
+    pub id: i32,
+    pub name: String,
+    pub gps: GeogPoint,
+    pub category: Option<String>,
+    pub created_at: NaiveDateTime,
+    /// OSM `opening_hours` syntax (see [`crate::opening_hours`]), e.g.
+    /// `"Mo-Fr 08:00-20:00; Sa 09:00-18:00"`. `None` means hours are unknown,
+    /// not that the location is always open.
+    pub opening_hours: Option<String>,
+}
+
+#[derive(Debug, Insertable, Serialize, Deserialize)]
+#[table_name = "locations"]
+pub struct NewLocation {
+    pub name: String,
+    pub gps: GeogPoint,
+    pub category: Option<String>,
+    pub opening_hours: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NewLocationJson {
+    pub name: String,
+    pub gps: [f64; 2],
+    pub category: Option<String>,
+    #[serde(default)]
+    pub opening_hours: Option<String>,
+}
+
+/// A canonical missing-good entry, e.g. "toilet paper" with aliases
+/// `["klopapier", "toilettenpapier"]` so free-text reports converge.
+#[derive(Debug, Queryable, Serialize, Deserialize)]
+#[table_name = "goods"]
+pub struct Good {
+    pub id: i32,
+    pub canonical_name: String,
+    pub aliases: Vec<String>,
+}
+
+#[derive(Debug, Insertable)]
+#[table_name = "goods"]
+pub struct NewGood {
+    pub canonical_name: String,
+    pub aliases: Vec<String>,
+}
+
+#[derive(Debug, Insertable, Queryable)]
+#[table_name = "checkin_goods"]
+pub struct CheckinGood {
+    pub checkin_id: i32,
+    pub good_id: i32,
+}
+
+#[derive(Debug, Queryable, Serialize)]
+pub struct GoodShortageCount {
+    pub canonical_name: String,
+    pub shortage_count: i64,
+}
+
+/// One photo attached to a checkin via `POST /v1/checkins/:id/photo`.
+/// `storage_key` is opaque to everything except the [`crate::blobs::BlobStore`]
+/// that wrote it — a relative file path for [`crate::blobs::FsBlobStore`], an
+/// object key for `S3BlobStore`.
+#[derive(Debug, Queryable, Serialize)]
+#[table_name = "checkin_photos"]
+pub struct CheckinPhoto {
+    pub id: i32,
+    pub checkin_id: i32,
+    pub storage_key: String,
+    pub content_type: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Insertable)]
+#[table_name = "checkin_photos"]
+pub struct NewCheckinPhoto {
+    pub checkin_id: i32,
+    pub storage_key: String,
+    pub content_type: String,
+}
+
+/// Recorded whenever a checkin is hard-deleted, so `GET /v1/sync` (see
+/// [`crate::sync`]) can tell offline clients "drop this from your local
+/// cache" instead of them never hearing about the deletion at all.
+#[derive(Debug, Queryable, Insertable, Serialize)]
+#[table_name = "checkin_tombstones"]
+pub struct CheckinTombstone {
+    pub checkin_id: i32,
+    pub deleted_at: NaiveDateTime,
+}
+
+impl From<NewLocationJson> for NewLocation {
+    fn from(location: NewLocationJson) -> NewLocation {
+        NewLocation {
+            name: location.name,
+            gps: GeogPoint {
+                x: location.gps[0],
+                y: location.gps[1],
+                srid: None,
+            },
+            category: location.category,
+            opening_hours: location.opening_hours,
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct CheckinsAroundRequest {
     pub gps: [f64; 2],
     pub radius: i32,
     pub offset: i32,
     pub limit: i32,
 }
+
+#[derive(Debug, Serialize, Deserialize, IntoParams)]
+pub struct ListCheckinsQuery {
+    #[serde(default = "default_page")]
+    pub page: i64,
+    #[serde(default = "default_per_page")]
+    pub per_page: i64,
+    #[serde(default)]
+    pub sort: CheckinSort,
+    pub format: Option<String>,
+    pub since: Option<NaiveDateTime>,
+    pub until: Option<NaiveDateTime>,
+    pub min_crowded_level: Option<i32>,
+    pub missing_good: Option<String>,
+    pub client_id: Option<String>,
+    /// Admin-only: restrict to checkins from users whose reputation score
+    /// has dropped below [`crate::reputation::OUTLIER_SCORE_THRESHOLD`].
+    /// Ignored by the public `/v1/checkins` listing.
+    #[serde(default)]
+    pub outliers_only: bool,
+}
+
+fn default_page() -> i64 {
+    1
+}
+
+fn default_per_page() -> i64 {
+    20
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckinSort {
+    CreatedAt,
+    CrowdedLevel,
+}
+
+impl Default for CheckinSort {
+    fn default() -> Self {
+        CheckinSort::CreatedAt
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub total: i64,
+    pub next_page: Option<i64>,
+}
+
+/// Maximum number of items accepted by `POST /v1/checkins/batch` in one request.
+pub const MAX_BATCH_SIZE: usize = 500;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchItemStatus {
+    Created,
+    ValidationError,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BatchItemResult {
+    pub index: usize,
+    pub status: BatchItemStatus,
+    pub id: Option<i32>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Queryable)]
+#[table_name = "clients"]
+pub struct Client {
+    pub id: i32,
+    pub client_id: String,
+    pub api_key: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Insertable)]
+#[table_name = "clients"]
+pub struct NewClient {
+    pub client_id: String,
+    pub api_key: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeleteCheckinQuery {
+    pub user_id: String,
+}
+
+/// A banned `client_id` or `user_id`, rejected on every future write.
+/// `subject_type` is `"client"` or `"user"`.
+#[derive(Debug, Queryable, Serialize)]
+#[table_name = "banned_clients"]
+pub struct BannedClient {
+    pub id: i32,
+    pub subject_type: String,
+    pub subject_id: String,
+    pub reason: Option<String>,
+    pub banned_at: NaiveDateTime,
+}
+
+#[derive(Debug, Insertable, Serialize)]
+#[table_name = "banned_clients"]
+pub struct NewBannedClient {
+    pub subject_type: String,
+    pub subject_id: String,
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BanRequest {
+    pub subject_type: String,
+    pub subject_id: String,
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, IntoParams)]
+pub struct NearbyQuery {
+    pub lat: f64,
+    pub lng: f64,
+    #[serde(default = "default_nearby_radius")]
+    pub radius: i32,
+    pub format: Option<String>,
+}
+
+fn default_nearby_radius() -> i32 {
+    1000
+}
+
+/// One grid cell from the clusters endpoint's `ST_SnapToGrid` aggregation.
+#[derive(Debug, QueryableByName)]
+pub struct ClusterRow {
+    #[sql_type = "diesel::sql_types::Double"]
+    pub lng: f64,
+    #[sql_type = "diesel::sql_types::Double"]
+    pub lat: f64,
+    #[sql_type = "diesel::sql_types::BigInt"]
+    pub count: i64,
+}
+
+/// A shop's registered alert hook: fires when `location_name`'s rolling
+/// average `crowded_level` crosses `crowded_level_threshold`. `secret` signs
+/// every delivered payload so the receiving endpoint can verify it actually
+/// came from us.
+#[derive(Debug, Queryable, Serialize)]
+#[table_name = "webhooks"]
+pub struct Webhook {
+    pub id: i32,
+    pub url: String,
+    #[serde(skip_serializing)]
+    pub secret: String,
+    pub location_name: String,
+    pub crowded_level_threshold: i32,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Insertable)]
+#[table_name = "webhooks"]
+pub struct NewWebhook {
+    pub url: String,
+    pub secret: String,
+    pub location_name: String,
+    pub crowded_level_threshold: i32,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NewWebhookJson {
+    pub url: String,
+    pub secret: String,
+    pub location_name: String,
+    pub crowded_level_threshold: i32,
+}
+
+impl From<NewWebhookJson> for NewWebhook {
+    fn from(webhook: NewWebhookJson) -> NewWebhook {
+        NewWebhook {
+            url: webhook.url,
+            secret: webhook.secret,
+            location_name: webhook.location_name,
+            crowded_level_threshold: webhook.crowded_level_threshold,
+        }
+    }
+}
+
+/// A saved place a user wants alerted on: fires a push notification when a
+/// new checkin lands within `radius_meters` of `gps` reporting one of
+/// `goods` missing. `QueryableByName` so [`crate::watches`] can match watches
+/// against a checkin with a single raw `ST_DWithin`/array-overlap query
+/// rather than round-tripping through the query builder per candidate row.
+#[derive(Debug, Queryable, QueryableByName, Serialize)]
+#[table_name = "watches"]
+pub struct Watch {
+    pub id: i32,
+    pub user_id: String,
+    pub client_id: String,
+    pub gps: GeogPoint,
+    pub radius_meters: i32,
+    pub goods: Vec<String>,
+    #[serde(skip_serializing)]
+    pub push_token: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Insertable)]
+#[table_name = "watches"]
+pub struct NewWatch {
+    pub user_id: String,
+    pub client_id: String,
+    pub gps: GeogPoint,
+    pub radius_meters: i32,
+    pub goods: Vec<String>,
+    pub push_token: String,
+}
+
+/// A user-chosen display name, opt-in via `POST /v1/users/:id/handle`. The
+/// leaderboard (see [`crate::leaderboard`]) only ever shows this, never the
+/// raw `user_id` -- contributors who haven't registered one simply don't
+/// appear on it.
+#[derive(Debug, Queryable, Serialize)]
+#[table_name = "user_handles"]
+pub struct UserHandle {
+    pub user_id: String,
+    pub handle: String,
+    pub created_at: NaiveDateTime,
+}
+
+pub const MAX_HANDLE_LENGTH: usize = 32;
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RegisterHandleJson {
+    pub handle: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NewWatchJson {
+    pub lat: f64,
+    pub lng: f64,
+    pub radius_meters: i32,
+    pub goods: Vec<String>,
+    pub push_token: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn valid_checkin() -> NewJsonCheckin {
+        NewJsonCheckin {
+            gps: [53.55, 9.97],
+            location_name: "some location".to_string(),
+            crowded_level: 3,
+            user_id: "some user".to_string(),
+            client_id: "some client".to_string(),
+            missing_goods: vec![String::from("flour")],
+            note: None,
+            observed_at: None,
+        }
+    }
+
+    #[test]
+    fn validate_accepts_well_formed_checkin() {
+        assert!(valid_checkin().validate().is_ok());
+    }
+
+    #[test]
+    fn validate_rejects_crowded_level_out_of_range() {
+        let mut checkin = valid_checkin();
+        checkin.crowded_level = 999;
+        let errors = checkin.validate().unwrap_err();
+        assert!(errors.iter().any(|e| e.contains("crowded_level")));
+    }
+
+    #[test]
+    fn validate_rejects_empty_location_name() {
+        let mut checkin = valid_checkin();
+        checkin.location_name = "   ".to_string();
+        let errors = checkin.validate().unwrap_err();
+        assert!(errors.iter().any(|e| e.contains("location_name")));
+    }
+
+    #[test]
+    fn validate_rejects_out_of_bounds_lat_lng() {
+        let mut checkin = valid_checkin();
+        checkin.gps = [120.0, 200.0];
+        let errors = checkin.validate().unwrap_err();
+        assert!(errors.iter().any(|e| e.contains("lat")));
+        assert!(errors.iter().any(|e| e.contains("lng")));
+    }
+
+    #[test]
+    fn validate_rejects_observed_at_in_the_future() {
+        let mut checkin = valid_checkin();
+        checkin.observed_at = Some(Utc::now() + Duration::hours(1));
+        let errors = checkin.validate().unwrap_err();
+        assert!(errors.iter().any(|e| e.contains("observed_at")));
+    }
+
+    #[test]
+    fn validate_rejects_observed_at_too_far_in_the_past() {
+        let mut checkin = valid_checkin();
+        checkin.observed_at = Some(Utc::now() - Duration::days(MAX_OBSERVED_AT_AGE_DAYS + 1));
+        let errors = checkin.validate().unwrap_err();
+        assert!(errors.iter().any(|e| e.contains("observed_at")));
+    }
+
+    #[test]
+    fn validate_accepts_recent_observed_at() {
+        let mut checkin = valid_checkin();
+        checkin.observed_at = Some(Utc::now() - Duration::hours(2));
+        assert!(checkin.validate().is_ok());
+    }
+}
@@ -0,0 +1,67 @@
+//! Machine-readable API contract, generated from the `#[utoipa::path(...)]`
+//! annotations on the handlers below rather than hand-maintained, so it
+//! can't drift from the actual routes the way a wiki page would. Exposed at
+//! `GET /api-doc/openapi.json`, with a bundled Swagger UI at `/docs` so the
+//! mobile teams can explore it without pulling in a codegen toolchain.
+//!
+//! Only the request/response shapes worth documenting are annotated so far
+//! (the list/create/get/nearby checkin endpoints) — extend this as the rest
+//! of the surface stabilizes rather than annotating everything up front.
+
+use utoipa::OpenApi;
+use warp::{Filter, Rejection, Reply};
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        crate::handlers::list_checkins,
+        crate::handlers::get_checkin,
+        crate::handlers::create_checkin,
+        crate::handlers::checkins_nearby,
+        crate::v2::create_checkin,
+    ),
+    components(schemas(
+        crate::model::NewJsonCheckin,
+        crate::model::UpdateCheckinJson,
+        crate::v2::LocationV2,
+        crate::v2::NewJsonCheckinV2,
+    )),
+    tags((name = "checkins", description = "Crowding reports"))
+)]
+struct ApiDoc;
+
+pub fn routes() -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    openapi_json().or(swagger_ui())
+}
+
+fn openapi_json() -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path!("api-doc" / "openapi.json")
+        .and(warp::get())
+        .map(|| warp::reply::json(&ApiDoc::openapi()))
+}
+
+fn swagger_ui() -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path("docs")
+        .and(warp::get())
+        .map(|| warp::reply::html(SWAGGER_UI_HTML))
+}
+
+const SWAGGER_UI_HTML: &str = r#"<!DOCTYPE html>
+<html>
+  <head>
+    <title>virus-vs-toilettenpapier API docs</title>
+    <link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist/swagger-ui.css" />
+  </head>
+  <body>
+    <div id="swagger-ui"></div>
+    <script src="https://unpkg.com/swagger-ui-dist/swagger-ui-bundle.js"></script>
+    <script>
+      window.onload = () => {
+        window.ui = SwaggerUIBundle({
+          url: "/api-doc/openapi.json",
+          dom_id: "#swagger-ui",
+        });
+      };
+    </script>
+  </body>
+</html>"#;
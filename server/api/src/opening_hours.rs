@@ -0,0 +1,154 @@
+//! A deliberately small subset of the [OSM `opening_hours`
+//! syntax](https://wiki.openstreetmap.org/wiki/Key:opening_hours), just
+//! enough to flag crowding reports filed while a location looks closed (see
+//! `handlers::create_checkin`) and to back the `open_now` filter on
+//! `GET /v1/locations`.
+//!
+//! Supported: semicolon-separated rules of `<days> <time>-<time>`, where
+//! `<days>` is a comma list of two-letter weekday codes or `Mo-Fr`-style
+//! ranges (defaulting to every day when omitted) and `<time>` is `HH:MM`.
+//! NOT supported: multiple time ranges per rule, holidays, `24/7`, comments,
+//! or any of the rest of the full grammar — an unparseable or unrecognized
+//! spec makes [`is_open_at`] return `None` rather than guess.
+
+use chrono::{Datelike, NaiveDateTime, NaiveTime, Weekday};
+
+struct Rule {
+    days: Vec<Weekday>,
+    start: NaiveTime,
+    end: NaiveTime,
+}
+
+/// Returns `Some(true)`/`Some(false)` if `spec` parses and covers `at`'s
+/// weekday, or `None` if `spec` is empty, unparseable, or simply doesn't say
+/// anything about that day — callers should treat `None` as "unknown",
+/// never as "closed".
+pub fn is_open_at(spec: &str, at: NaiveDateTime) -> Option<bool> {
+    let rules = parse(spec)?;
+    let weekday = at.weekday();
+    let time = at.time();
+    let mut matched_day = false;
+    for rule in &rules {
+        if rule.days.contains(&weekday) {
+            matched_day = true;
+            if rule.start <= time && time < rule.end {
+                return Some(true);
+            }
+        }
+    }
+    if matched_day {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+fn parse(spec: &str) -> Option<Vec<Rule>> {
+    let spec = spec.trim();
+    if spec.is_empty() {
+        return None;
+    }
+    let rules: Option<Vec<Rule>> = spec.split(';').map(|rule| parse_rule(rule.trim())).collect();
+    rules.filter(|rules| !rules.is_empty())
+}
+
+fn parse_rule(rule: &str) -> Option<Rule> {
+    let mut parts = rule.split_whitespace();
+    let first = parts.next()?;
+    let (days, time_range) = if let Some(time_range) = parts.next() {
+        (parse_days(first)?, time_range)
+    } else {
+        // No day spec: the whole rule is just a time range, applying every day.
+        (ALL_WEEKDAYS.to_vec(), first)
+    };
+    let (start, end) = time_range.split_once('-')?;
+    Some(Rule {
+        days,
+        start: NaiveTime::parse_from_str(start, "%H:%M").ok()?,
+        end: NaiveTime::parse_from_str(end, "%H:%M").ok()?,
+    })
+}
+
+const ALL_WEEKDAYS: [Weekday; 7] = [
+    Weekday::Mon,
+    Weekday::Tue,
+    Weekday::Wed,
+    Weekday::Thu,
+    Weekday::Fri,
+    Weekday::Sat,
+    Weekday::Sun,
+];
+
+fn parse_days(spec: &str) -> Option<Vec<Weekday>> {
+    let mut days = Vec::new();
+    for part in spec.split(',') {
+        if let Some((from, to)) = part.split_once('-') {
+            let from = weekday_from_code(from)?;
+            let to = weekday_from_code(to)?;
+            let mut day = from;
+            loop {
+                days.push(day);
+                if day == to {
+                    break;
+                }
+                day = day.succ();
+            }
+        } else {
+            days.push(weekday_from_code(part)?);
+        }
+    }
+    Some(days)
+}
+
+fn weekday_from_code(code: &str) -> Option<Weekday> {
+    match code {
+        "Mo" => Some(Weekday::Mon),
+        "Tu" => Some(Weekday::Tue),
+        "We" => Some(Weekday::Wed),
+        "Th" => Some(Weekday::Thu),
+        "Fr" => Some(Weekday::Fri),
+        "Sa" => Some(Weekday::Sat),
+        "Su" => Some(Weekday::Sun),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn at(y: i32, m: u32, d: u32, h: u32, min: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd(y, m, d).and_hms(h, min, 0)
+    }
+
+    #[test]
+    fn open_during_weekday_hours() {
+        // 2020-07-13 is a Monday.
+        assert_eq!(is_open_at("Mo-Fr 08:00-20:00", at(2020, 7, 13, 12, 0)), Some(true));
+    }
+
+    #[test]
+    fn closed_outside_weekday_hours() {
+        assert_eq!(is_open_at("Mo-Fr 08:00-20:00", at(2020, 7, 13, 21, 0)), Some(false));
+    }
+
+    #[test]
+    fn unmentioned_day_is_unknown() {
+        // 2020-07-12 is a Sunday, not covered by "Mo-Fr".
+        assert_eq!(is_open_at("Mo-Fr 08:00-20:00", at(2020, 7, 12, 12, 0)), None);
+    }
+
+    #[test]
+    fn multiple_rules_combine_across_days() {
+        let spec = "Mo-Fr 08:00-20:00; Sa 09:00-18:00";
+        assert_eq!(is_open_at(spec, at(2020, 7, 18, 10, 0)), Some(true)); // Saturday
+        assert_eq!(is_open_at(spec, at(2020, 7, 19, 10, 0)), None); // Sunday
+    }
+
+    #[test]
+    fn unparseable_spec_is_unknown() {
+        assert_eq!(is_open_at("24/7", at(2020, 7, 13, 12, 0)), None);
+        assert_eq!(is_open_at("", at(2020, 7, 13, 12, 0)), None);
+    }
+}
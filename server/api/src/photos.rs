@@ -0,0 +1,150 @@
+//! `POST`/`GET /v1/checkins/:id/photo`: attaching and serving a photo of the
+//! empty shelves a checkin reports. Blob bytes go through a
+//! [`crate::blobs::BlobStore`]; only the resulting `storage_key` and
+//! `content_type` are kept in Postgres (see the `checkin_photos` table).
+
+use crate::auth::AuthenticatedClient;
+use crate::blobs::{BlobDelivery, SharedBlobStore};
+use crate::db::Db;
+use crate::errors::ApiError;
+use crate::model::{Checkin, CheckinPhoto, NewCheckinPhoto};
+use bytes::Buf;
+use diesel::prelude::*;
+use futures::TryStreamExt;
+use warp::http::{Response, StatusCode};
+use warp::{Rejection, Reply};
+
+/// One part, keyed by field name, is accepted: `photo`. Anything else in the
+/// form is ignored rather than rejected, so clients can send extra metadata
+/// fields without the upload failing.
+const PHOTO_FIELD_NAME: &str = "photo";
+
+pub async fn upload_photo(
+    checkin_id: i32,
+    client: AuthenticatedClient,
+    db: Db,
+    blob_store: SharedBlobStore,
+    mut form: warp::multipart::FormData,
+) -> Result<impl Reply, Rejection> {
+    let checkin: Checkin = db
+        .run(move |conn| {
+            use crate::schema::checkins::dsl;
+            dsl::checkins.find(checkin_id).first(conn)
+        })
+        .await
+        .map_err(ApiError::from)
+        .map_err(warp::reject::custom)?;
+
+    if checkin.client_id != client.client_id {
+        return Err(warp::reject::custom(ApiError::Validation(
+            "not authorized to attach a photo to this checkin".to_string(),
+        )));
+    }
+
+    let mut photo: Option<(String, Vec<u8>)> = None;
+    while let Some(mut part) = form
+        .try_next()
+        .await
+        .map_err(|e| warp::reject::custom(ApiError::Validation(e.to_string())))?
+    {
+        if part.name() != PHOTO_FIELD_NAME {
+            continue;
+        }
+        let content_type = part.content_type().unwrap_or("application/octet-stream").to_string();
+        let mut bytes = Vec::new();
+        while let Some(mut chunk) = part
+            .data()
+            .await
+            .transpose()
+            .map_err(|e| warp::reject::custom(ApiError::Validation(e.to_string())))?
+        {
+            while chunk.has_remaining() {
+                let len = chunk.bytes().len();
+                bytes.extend_from_slice(chunk.bytes());
+                chunk.advance(len);
+            }
+        }
+        photo = Some((content_type, bytes));
+        break;
+    }
+
+    let (content_type, bytes) = photo.ok_or_else(|| {
+        warp::reject::custom(ApiError::Validation(format!(
+            "missing `{}` form field",
+            PHOTO_FIELD_NAME
+        )))
+    })?;
+
+    let storage_key = storage_key_for(checkin_id, &content_type);
+
+    blob_store
+        .put(&storage_key, &content_type, bytes)
+        .await
+        .map_err(|e| warp::reject::custom(ApiError::DbQuery(e.to_string())))?;
+
+    let new_photo = NewCheckinPhoto {
+        checkin_id,
+        storage_key,
+        content_type,
+    };
+    let photo: CheckinPhoto = db
+        .run(move |conn| {
+            use crate::schema::checkin_photos::dsl;
+            diesel::insert_into(dsl::checkin_photos)
+                .values(&new_photo)
+                .on_conflict(dsl::checkin_id)
+                .do_update()
+                .set((
+                    dsl::storage_key.eq(&new_photo.storage_key),
+                    dsl::content_type.eq(&new_photo.content_type),
+                ))
+                .get_result(conn)
+        })
+        .await
+        .map_err(ApiError::from)
+        .map_err(warp::reject::custom)?;
+
+    Ok(warp::reply::json(&photo))
+}
+
+pub async fn get_photo(checkin_id: i32, db: Db, blob_store: SharedBlobStore) -> Result<impl Reply, Rejection> {
+    let photo: CheckinPhoto = db
+        .run(move |conn| {
+            use crate::schema::checkin_photos::dsl;
+            dsl::checkin_photos
+                .filter(dsl::checkin_id.eq(checkin_id))
+                .first(conn)
+        })
+        .await
+        .map_err(ApiError::from)
+        .map_err(warp::reject::custom)?;
+
+    match blob_store
+        .fetch(&photo.storage_key)
+        .await
+        .map_err(|e| warp::reject::custom(ApiError::DbQuery(e.to_string())))?
+    {
+        BlobDelivery::Bytes(bytes) => Ok(Response::builder()
+            .header("Content-Type", photo.content_type)
+            .body(bytes)
+            .expect("build photo response")),
+        BlobDelivery::Redirect(url) => Ok(Response::builder()
+            .status(StatusCode::FOUND)
+            .header("Location", url)
+            .body(Vec::new())
+            .expect("build photo redirect")),
+    }
+}
+
+/// A storage key that can't collide across checkins and sorts with its
+/// checkin for easy inspection of the filesystem/bucket layout. One checkin
+/// has at most one photo (`checkin_photos.checkin_id` is unique), so the key
+/// doesn't need a random suffix.
+fn storage_key_for(checkin_id: i32, content_type: &str) -> String {
+    let extension = match content_type {
+        "image/png" => "png",
+        "image/webp" => "webp",
+        _ => "jpg",
+    };
+    format!("checkins/{}/photo.{}", checkin_id, extension)
+}
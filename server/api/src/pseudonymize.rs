@@ -0,0 +1,167 @@
+//! HMAC-based pseudonymization of the `user_id` clients report crowding
+//! observations under (see `handlers::create_checkin`). Deliberately scoped
+//! to `user_id` alone: `client_id` identifies the paying store integration
+//! tied to its own API key (see [`crate::auth::bearer_auth`]), not an end
+//! user, so pseudonymizing it would break auth/billing attribution without
+//! addressing the actual privacy concern. `watches`/`webhooks` identifiers
+//! are a separate, smaller surface and out of scope for this pass.
+//!
+//! The HMAC key rotates monthly — the base secret mixed with `YYYY-MM` — so
+//! the same raw id still hashes to the same pseudonym for dedup/rate-limit
+//! and moderation purposes (see [`crate::admin::is_banned`],
+//! [`crate::reputation`]) within a month, but two different months of
+//! reports can no longer be linked back to the same person. `None` (no
+//! `PSEUDONYMIZATION_SECRET` configured) disables the feature entirely,
+//! matching every other optional subsystem in this codebase
+//! ([`crate::geocoding`], [`crate::push`]) — existing deployments keep
+//! storing raw ids until an operator opts in.
+
+use chrono::{Datelike, Utc};
+use diesel::prelude::*;
+use diesel::PgConnection;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::sync::Arc;
+use warp::Filter;
+
+/// Shared pseudonymizer injected into filters the same way `Db`/`Cache` are.
+/// `None` means the subsystem is disabled.
+pub type SharedPseudonymizer = Option<Arc<Pseudonymizer>>;
+
+/// How many months back [`Pseudonymizer::candidates`] searches. `apply`
+/// always hashes under the *current* rotation month, so a raw id supplied
+/// well after the checkin it needs to match was written would otherwise
+/// never hash back to the stored pseudonym — silently starving erasure and
+/// self-service delete instead of matching. Bounded rather than unbounded so
+/// those lookups can't be made to scan arbitrarily far back; two years
+/// covers any plausible gap given there's no `retention_days` default.
+const LOOKBACK_MONTHS: i64 = 24;
+
+pub struct Pseudonymizer {
+    secret: String,
+}
+
+impl Pseudonymizer {
+    pub fn new(secret: String) -> Self {
+        Pseudonymizer { secret }
+    }
+
+    /// Pseudonymizes `raw` under the current calendar month's rotation key.
+    pub fn apply(&self, raw: &str) -> String {
+        let now = Utc::now();
+        Self::apply_for_month(&self.secret, now.year(), now.month(), raw)
+    }
+
+    /// Every pseudonym `raw` could currently be stored under: the current
+    /// rotation month plus up to [`LOOKBACK_MONTHS`] prior months. Use this
+    /// instead of [`Self::apply`] wherever a caller-supplied raw id has to
+    /// match a row that may have been pseudonymized in an earlier month —
+    /// erasure and self-service delete, not a one-off lookup like
+    /// `export_user_checkins`.
+    pub fn candidates(&self, raw: &str) -> Vec<String> {
+        let now = Utc::now();
+        (0..=LOOKBACK_MONTHS)
+            .map(|offset| {
+                let (year, month) = month_minus(now.year(), now.month(), offset);
+                Self::apply_for_month(&self.secret, year, month, raw)
+            })
+            .collect()
+    }
+
+    fn apply_for_month(secret: &str, year: i32, month: u32, raw: &str) -> String {
+        let key = format!("{}:{:04}-{:02}", secret, year, month);
+        let mut mac = Hmac::<Sha256>::new(key.as_bytes()).expect("HMAC accepts any key length");
+        mac.input(raw.as_bytes());
+        mac.result()
+            .code()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+}
+
+/// `month` (1-12) minus `offset` whole months, rolling `year` over as needed.
+fn month_minus(year: i32, month: u32, offset: i64) -> (i32, u32) {
+    let zero_based = i64::from(month) - 1 - offset;
+    let year_offset = zero_based.div_euclid(12);
+    let month_zero_based = zero_based.rem_euclid(12);
+    (year + year_offset as i32, month_zero_based as u32 + 1)
+}
+
+/// Injects [`SharedPseudonymizer`] into filters the same way
+/// [`crate::geocoding::filter`]/[`crate::push::filter`] do.
+pub fn filter(
+    pseudonymizer: SharedPseudonymizer,
+) -> impl Filter<Extract = (SharedPseudonymizer,), Error = std::convert::Infallible> + Clone {
+    warp::any().map(move || pseudonymizer.clone())
+}
+
+/// One-off migration for rows written before this subsystem existed:
+/// rewrites every `checkins.user_id` and `user_reputation.user_id` to its
+/// pseudonym under the *current* rotation month, so reputation lookups
+/// (keyed by `user_id`, see [`crate::reputation`]) keep matching going
+/// forward. Run via `cargo run -- pseudonymize-migrate` with
+/// `PSEUDONYMIZATION_SECRET` set. Meant to run exactly once, shortly after
+/// turning the secret on — rerunning in a later month would hash
+/// already-pseudonymized ids a second time.
+pub fn migrate_existing_rows(conn: &PgConnection, pseudonymizer: &Pseudonymizer) -> QueryResult<usize> {
+    use crate::schema::{checkins, user_reputation};
+
+    conn.transaction(|| {
+        let raw_ids: Vec<String> = checkins::table.select(checkins::user_id).distinct().load(conn)?;
+
+        let mut migrated = 0;
+        for raw_id in raw_ids {
+            let pseudonym = pseudonymizer.apply(&raw_id);
+            migrated += diesel::update(checkins::table.filter(checkins::user_id.eq(&raw_id)))
+                .set(checkins::user_id.eq(&pseudonym))
+                .execute(conn)?;
+            diesel::update(user_reputation::table.filter(user_reputation::user_id.eq(&raw_id)))
+                .set(user_reputation::user_id.eq(&pseudonym))
+                .execute(conn)?;
+        }
+        Ok(migrated)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_raw_id_same_month_matches() {
+        let a = Pseudonymizer::apply_for_month("secret", 2020, 7, "alice");
+        let b = Pseudonymizer::apply_for_month("secret", 2020, 7, "alice");
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn different_months_diverge() {
+        let a = Pseudonymizer::apply_for_month("secret", 2020, 7, "alice");
+        let b = Pseudonymizer::apply_for_month("secret", 2020, 8, "alice");
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn different_secrets_diverge() {
+        let a = Pseudonymizer::apply_for_month("secret-a", 2020, 7, "alice");
+        let b = Pseudonymizer::apply_for_month("secret-b", 2020, 7, "alice");
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn month_minus_rolls_over_year_boundary() {
+        assert_eq!(month_minus(2020, 1, 1), (2019, 12));
+        assert_eq!(month_minus(2020, 7, 0), (2020, 7));
+        assert_eq!(month_minus(2020, 7, 7), (2019, 12));
+        assert_eq!(month_minus(2020, 7, 19), (2018, 12));
+    }
+
+    #[test]
+    fn candidates_includes_the_current_months_pseudonym() {
+        let pseudonymizer = Pseudonymizer::new("secret".to_string());
+        let candidates = pseudonymizer.candidates("alice");
+        assert!(candidates.contains(&pseudonymizer.apply("alice")));
+        assert_eq!(candidates.len() as i64, LOOKBACK_MONTHS + 1);
+    }
+}
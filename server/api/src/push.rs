@@ -0,0 +1,111 @@
+//! Optional push-notification delivery for watch alerts (see
+//! [`crate::watches`]). Deliberately pluggable: `PushProvider` returns a
+//! `BoxFuture` directly rather than depending on `async-trait`, matching the
+//! async-callback convention [`crate::geocoding`] already uses for the other
+//! optional outbound-HTTP subsystem. Delivery runs in a detached task after
+//! the checkin response is sent, so a slow or unreachable push gateway never
+//! adds latency to `POST /v1/checkins`.
+
+use futures::future::BoxFuture;
+use serde::Serialize;
+use std::fmt;
+use std::sync::Arc;
+use warp::Filter;
+
+#[derive(Debug)]
+pub enum PushError {
+    Http(reqwest::Error),
+}
+
+impl fmt::Display for PushError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PushError::Http(e) => write!(f, "push request failed: {}", e),
+        }
+    }
+}
+
+impl From<reqwest::Error> for PushError {
+    fn from(e: reqwest::Error) -> Self {
+        PushError::Http(e)
+    }
+}
+
+pub trait PushProvider: Send + Sync {
+    /// Sends a single notification to `device_token`, or returns `Err` if the
+    /// gateway rejected or couldn't be reached for it.
+    fn send(
+        &self,
+        device_token: &str,
+        title: &str,
+        body: &str,
+    ) -> BoxFuture<'static, Result<(), PushError>>;
+}
+
+/// Shared, optionally-absent push provider injected into filters the same way
+/// `Cache`/`Db` are. `None` means the subsystem is disabled, e.g. because no
+/// gateway credentials are configured for this deployment.
+pub type SharedPushProvider = Option<Arc<dyn PushProvider>>;
+
+#[derive(Serialize)]
+struct FcmNotification<'a> {
+    title: &'a str,
+    body: &'a str,
+}
+
+#[derive(Serialize)]
+struct FcmMessage<'a> {
+    to: &'a str,
+    notification: FcmNotification<'a>,
+}
+
+/// Sends via the Firebase Cloud Messaging legacy HTTP API, which is enough to
+/// reach both Android and (via APNs-bridging) iOS devices off a single server
+/// key, so there's no separate APNs-only code path yet.
+pub struct FcmPushProvider {
+    server_key: String,
+    client: reqwest::Client,
+}
+
+impl FcmPushProvider {
+    pub fn new(server_key: String) -> Self {
+        FcmPushProvider {
+            server_key,
+            client: reqwest::Client::new(),
+        }
+    }
+}
+
+impl PushProvider for FcmPushProvider {
+    fn send(
+        &self,
+        device_token: &str,
+        title: &str,
+        body: &str,
+    ) -> BoxFuture<'static, Result<(), PushError>> {
+        let client = self.client.clone();
+        let authorization = format!("key={}", self.server_key);
+        let message = FcmMessage {
+            to: device_token,
+            notification: FcmNotification { title, body },
+        };
+        let payload = serde_json::to_string(&message).expect("serialize FCM message");
+        Box::pin(async move {
+            client
+                .post("https://fcm.googleapis.com/fcm/send")
+                .header("Authorization", authorization)
+                .header("Content-Type", "application/json")
+                .body(payload)
+                .send()
+                .await?
+                .error_for_status()?;
+            Ok(())
+        })
+    }
+}
+
+pub fn filter(
+    push_provider: SharedPushProvider,
+) -> impl Filter<Extract = (SharedPushProvider,), Error = std::convert::Infallible> + Clone {
+    warp::any().map(move || push_provider.clone())
+}
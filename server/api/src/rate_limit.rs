@@ -0,0 +1,45 @@
+//! Fixed-window per-key rate limiting, in-memory. Good enough for a single
+//! server instance; a multi-instance deployment would need this backed by
+//! Redis, but that's more than this app needs today.
+
+use crate::errors::ApiError;
+use dashmap::DashMap;
+use lazy_static::lazy_static;
+use std::time::{Duration, Instant};
+use warp::Rejection;
+
+const WINDOW: Duration = Duration::from_secs(60);
+
+struct Window {
+    started_at: Instant,
+    count: u32,
+}
+
+lazy_static! {
+    static ref WINDOWS: DashMap<String, Window> = DashMap::new();
+}
+
+/// Counts one request against `key`'s current window, rejecting with
+/// `ApiError::RateLimited` once `limit_per_minute` is exceeded.
+pub fn check(key: &str, limit_per_minute: u32) -> Result<(), Rejection> {
+    let now = Instant::now();
+    let mut window = WINDOWS.entry(key.to_string()).or_insert_with(|| Window {
+        started_at: now,
+        count: 0,
+    });
+
+    if now.duration_since(window.started_at) >= WINDOW {
+        window.started_at = now;
+        window.count = 0;
+    }
+
+    window.count += 1;
+
+    if window.count > limit_per_minute {
+        let elapsed = now.duration_since(window.started_at);
+        let retry_after = WINDOW.checked_sub(elapsed).unwrap_or_default().as_secs().max(1);
+        return Err(warp::reject::custom(ApiError::RateLimited(retry_after)));
+    }
+
+    Ok(())
+}
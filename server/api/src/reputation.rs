@@ -0,0 +1,120 @@
+//! Per-user trust scoring. A single checkin is compared against other
+//! contemporaneous reports near the same spot; agreeing nudges the reporting
+//! user's score up, disagreeing nudges it down. `create_checkin`/
+//! `create_checkins_batch` call [`record_agreement`] in the same transaction
+//! as the insert, so the score is always current by the time a stats request
+//! or admin review reads it.
+
+use crate::model::Checkin;
+use crate::schema::user_reputation;
+use chrono::{Duration, Utc};
+use diesel::prelude::*;
+use diesel::sql_types::{Int4, Text, Timestamp};
+use std::collections::HashMap;
+
+/// Score assigned to a user with no history yet.
+pub const DEFAULT_SCORE: f64 = 1.0;
+const MIN_SCORE: f64 = 0.1;
+const MAX_SCORE: f64 = 2.0;
+const SCORE_STEP: f64 = 0.1;
+
+/// Users scoring below this are surfaced to admins as likely noise.
+pub const OUTLIER_SCORE_THRESHOLD: f64 = 0.5;
+
+/// How close two reports must be to count as describing the same spot.
+const NEARBY_RADIUS_METERS: i32 = 200;
+/// How close in time two reports must be to count as contemporaneous.
+const AGREEMENT_WINDOW_MINUTES: i64 = 30;
+/// `crowded_level` difference within which two reports count as agreeing.
+const AGREEMENT_TOLERANCE: i32 = 1;
+
+#[derive(QueryableByName)]
+struct NearbyLevel {
+    #[sql_type = "Int4"]
+    crowded_level: i32,
+}
+
+/// Compares `checkin` against other users' reports near the same point and
+/// time, and nudges the reporting user's score accordingly. A no-op if no
+/// other reports exist yet to compare against.
+pub fn record_agreement(conn: &PgConnection, checkin: &Checkin) -> QueryResult<()> {
+    let window_start = checkin.observed_at - Duration::minutes(AGREEMENT_WINDOW_MINUTES);
+    let window_end = checkin.observed_at + Duration::minutes(AGREEMENT_WINDOW_MINUTES);
+
+    let q = format!(
+        "select crowded_level from checkins \
+         where id != $1 and user_id != $2 and not hidden \
+         and observed_at between $3 and $4 \
+         and ST_DWithin(gps, (select gps from checkins where id = $1), {}, false)",
+        NEARBY_RADIUS_METERS
+    );
+    let nearby: Vec<NearbyLevel> = diesel::sql_query(q)
+        .bind::<Int4, _>(checkin.id)
+        .bind::<Text, _>(&checkin.user_id)
+        .bind::<Timestamp, _>(window_start)
+        .bind::<Timestamp, _>(window_end)
+        .get_results(conn)?;
+
+    if nearby.is_empty() {
+        return Ok(());
+    }
+
+    let agreeing = nearby
+        .iter()
+        .filter(|n| (n.crowded_level - checkin.crowded_level).abs() <= AGREEMENT_TOLERANCE)
+        .count();
+    let agreement_ratio = agreeing as f64 / nearby.len() as f64;
+    let delta = if agreement_ratio >= 0.5 {
+        SCORE_STEP
+    } else {
+        -SCORE_STEP
+    };
+
+    let updated = (score(conn, &checkin.user_id)? + delta).max(MIN_SCORE).min(MAX_SCORE);
+    let now = Utc::now().naive_utc();
+
+    diesel::insert_into(user_reputation::table)
+        .values((
+            user_reputation::user_id.eq(&checkin.user_id),
+            user_reputation::score.eq(updated),
+            user_reputation::updated_at.eq(now),
+        ))
+        .on_conflict(user_reputation::user_id)
+        .do_update()
+        .set((
+            user_reputation::score.eq(updated),
+            user_reputation::updated_at.eq(now),
+        ))
+        .execute(conn)?;
+
+    Ok(())
+}
+
+/// `user_id`'s current score, or [`DEFAULT_SCORE`] if they have no history.
+pub fn score(conn: &PgConnection, user_id: &str) -> QueryResult<f64> {
+    let found: Option<f64> = user_reputation::table
+        .filter(user_reputation::user_id.eq(user_id))
+        .select(user_reputation::score)
+        .first(conn)
+        .optional()?;
+    Ok(found.unwrap_or(DEFAULT_SCORE))
+}
+
+/// Scores for every id in `user_ids` that has a row; ids absent from the
+/// result default to [`DEFAULT_SCORE`] in the caller.
+pub fn scores_for(conn: &PgConnection, user_ids: &[String]) -> QueryResult<HashMap<String, f64>> {
+    let rows: Vec<(String, f64)> = user_reputation::table
+        .filter(user_reputation::user_id.eq_any(user_ids))
+        .select((user_reputation::user_id, user_reputation::score))
+        .load(conn)?;
+    Ok(rows.into_iter().collect())
+}
+
+/// User ids whose score has dropped below [`OUTLIER_SCORE_THRESHOLD`], for
+/// the admin "flagged" checkin review queue.
+pub fn outlier_user_ids(conn: &PgConnection) -> QueryResult<Vec<String>> {
+    user_reputation::table
+        .filter(user_reputation::score.lt(OUTLIER_SCORE_THRESHOLD))
+        .select(user_reputation::user_id)
+        .load(conn)
+}
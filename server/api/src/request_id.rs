@@ -0,0 +1,40 @@
+//! Mints or forwards a per-request ID so a single report can be traced
+//! across client retries, logs, and support tickets. An incoming
+//! `X-Request-Id` header is trusted and echoed back unchanged; otherwise a
+//! new one is generated.
+//!
+//! Warp 0.2's filter combinators don't give us a clean way to keep a single
+//! tracing span open across an entire nested filter tree, so the ID is
+//! threaded explicitly into the handlers that log, and stamped onto every
+//! response (success or error) by wrapping the whole route tree once in
+//! `main.rs`.
+
+use uuid::Uuid;
+use warp::http::HeaderValue;
+use warp::{Filter, Rejection, Reply};
+
+pub const HEADER_NAME: &str = "x-request-id";
+
+/// Extracts the incoming `X-Request-Id` header, or mints a new UUID.
+pub fn filter() -> impl Filter<Extract = (String,), Error = std::convert::Infallible> + Clone {
+    warp::header::optional::<String>(HEADER_NAME)
+        .map(|existing: Option<String>| existing.unwrap_or_else(|| Uuid::new_v4().to_string()))
+}
+
+/// Wraps `routes` so every response, success or error, carries the request's
+/// `X-Request-Id`.
+pub fn with_header<F, T>(
+    routes: F,
+) -> impl Filter<Extract = (warp::reply::Response,), Error = Rejection> + Clone
+where
+    F: Filter<Extract = (T,), Error = Rejection> + Clone,
+    T: Reply,
+{
+    filter().and(routes).map(|request_id: String, reply: T| {
+        let mut response = reply.into_response();
+        if let Ok(value) = HeaderValue::from_str(&request_id) {
+            response.headers_mut().insert(HEADER_NAME, value);
+        }
+        response
+    })
+}
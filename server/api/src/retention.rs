@@ -0,0 +1,68 @@
+//! GDPR data retention: a background task that periodically deletes checkins
+//! older than `RETENTION_DAYS`. Checkins carry `user_id`/`client_id` and a
+//! precise location, so they can't be kept indefinitely. Disabled unless
+//! `RETENTION_DAYS` is configured; `main.rs` only calls `spawn` when it is.
+
+use crate::db::{Db, DbError};
+use chrono::{Duration, Utc};
+use diesel::prelude::*;
+use diesel::PgConnection;
+use std::time::Duration as StdDuration;
+
+/// How often the job checks for rows to purge, independent of how long rows
+/// are kept.
+const RUN_INTERVAL: StdDuration = StdDuration::from_secs(60 * 60);
+
+/// Spawns the periodic purge task. Runs until the process exits.
+pub fn spawn(db: Db, retention_days: i64) {
+    tokio::task::spawn(async move {
+        let mut interval = tokio::time::interval(RUN_INTERVAL);
+        loop {
+            interval.tick().await;
+            match purge_once(&db, retention_days).await {
+                Ok(purged) => {
+                    crate::metrics::record_retention_purge(purged);
+                    info!(purged, retention_days, "retention job purged old checkins");
+                }
+                Err(e) => error!("retention job failed: {}", e),
+            }
+        }
+    });
+}
+
+async fn purge_once(db: &Db, retention_days: i64) -> Result<usize, DbError> {
+    db.run(move |conn| purge_older_than(conn, retention_days)).await
+}
+
+/// Deletes every checkin older than `retention_days`, the query both the
+/// background job and the `purge` CLI subcommand run. Pairs each deletion
+/// with a `checkin_tombstones` row in the same transaction, the same as
+/// `handlers::delete_checkin`/`erase_user_checkins`, so `/v1/sync` clients
+/// that cached a since-purged checkin still learn it's gone.
+pub fn purge_older_than(conn: &PgConnection, retention_days: i64) -> QueryResult<usize> {
+    use crate::model::CheckinTombstone;
+    use crate::schema::checkin_tombstones;
+    use crate::schema::checkins::dsl::*;
+
+    conn.transaction(|| {
+        let cutoff = Utc::now().naive_utc() - Duration::days(retention_days);
+        let removed: Vec<crate::model::Checkin> =
+            diesel::delete(checkins.filter(created_at.lt(cutoff))).get_results(conn)?;
+
+        let now = Utc::now().naive_utc();
+        let tombstones: Vec<CheckinTombstone> = removed
+            .iter()
+            .map(|checkin| CheckinTombstone {
+                checkin_id: checkin.id,
+                deleted_at: now,
+            })
+            .collect();
+        if !tombstones.is_empty() {
+            diesel::insert_into(checkin_tombstones::table)
+                .values(&tombstones)
+                .execute(conn)?;
+        }
+
+        Ok(removed.len())
+    })
+}
@@ -13,6 +13,93 @@ table! {
         user_id -> Text,
         client_id -> Text,
         created_at -> Timestamp,
+        location_id -> Nullable<Int4>,
+        updated_at -> Timestamp,
+        hidden -> Bool,
+        note -> Nullable<Text>,
+        observed_at -> Timestamp,
+        idempotency_key -> Nullable<Text>,
+        geocoded_address -> Nullable<Text>,
+        region -> Text,
+    }
+}
+
+table! {
+    audit_log (id) {
+        id -> Int4,
+        actor -> Text,
+        action -> Text,
+        route -> Text,
+        entity_type -> Text,
+        entity_id -> Text,
+        diff -> Jsonb,
+        request_id -> Text,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    banned_clients (id) {
+        id -> Int4,
+        subject_type -> Text,
+        subject_id -> Text,
+        reason -> Nullable<Text>,
+        banned_at -> Timestamp,
+    }
+}
+
+table! {
+    clients (id) {
+        id -> Int4,
+        client_id -> Text,
+        api_key -> Text,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    checkin_tombstones (checkin_id) {
+        checkin_id -> Int4,
+        deleted_at -> Timestamp,
+    }
+}
+
+table! {
+    checkin_goods (checkin_id, good_id) {
+        checkin_id -> Int4,
+        good_id -> Int4,
+    }
+}
+
+table! {
+    goods (id) {
+        id -> Int4,
+        canonical_name -> Text,
+        aliases -> Array<Text>,
+    }
+}
+
+table! {
+    checkin_photos (id) {
+        id -> Int4,
+        checkin_id -> Int4,
+        storage_key -> Text,
+        content_type -> Text,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use diesel_geography::sql_types::*;
+
+    locations (id) {
+        id -> Int4,
+        name -> Text,
+        gps -> Geography,
+        category -> Nullable<Text>,
+        created_at -> Timestamp,
+        opening_hours -> Nullable<Text>,
     }
 }
 
@@ -29,4 +116,67 @@ table! {
     }
 }
 
-allow_tables_to_appear_in_same_query!(checkins, spatial_ref_sys,);
+table! {
+    webhooks (id) {
+        id -> Int4,
+        url -> Text,
+        secret -> Text,
+        location_name -> Text,
+        crowded_level_threshold -> Int4,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    user_reputation (user_id) {
+        user_id -> Text,
+        score -> Double,
+        updated_at -> Timestamp,
+    }
+}
+
+table! {
+    user_handles (user_id) {
+        user_id -> Text,
+        handle -> Text,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use diesel_geography::sql_types::*;
+
+    watches (id) {
+        id -> Int4,
+        user_id -> Text,
+        client_id -> Text,
+        gps -> Geography,
+        radius_meters -> Int4,
+        goods -> Array<Text>,
+        push_token -> Text,
+        created_at -> Timestamp,
+    }
+}
+
+joinable!(checkins -> locations (location_id));
+joinable!(checkin_goods -> checkins (checkin_id));
+joinable!(checkin_goods -> goods (good_id));
+joinable!(checkin_photos -> checkins (checkin_id));
+
+allow_tables_to_appear_in_same_query!(
+    audit_log,
+    banned_clients,
+    checkin_goods,
+    checkin_photos,
+    checkin_tombstones,
+    checkins,
+    clients,
+    goods,
+    locations,
+    spatial_ref_sys,
+    user_handles,
+    user_reputation,
+    watches,
+    webhooks,
+);
@@ -0,0 +1,210 @@
+//! Server-side aggregation for `GET /v1/locations/:name/stats`. The raw
+//! checkin list is useless to the frontend without this: it wants a single
+//! crowding summary per location, not hundreds of rows to reduce client-side.
+
+use crate::cache::Cache;
+use crate::db::Db;
+use crate::errors::ApiError;
+use crate::model::Checkin;
+use chrono::{Duration, Utc};
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use warp::{Rejection, Reply};
+
+#[derive(Debug, Deserialize)]
+pub struct StatsQuery {
+    #[serde(default = "default_window_hours")]
+    pub window_hours: i64,
+}
+
+fn default_window_hours() -> i64 {
+    24 * 7
+}
+
+#[derive(Debug, Serialize)]
+pub struct LocationStats {
+    pub location_name: String,
+    pub checkin_count: i64,
+    pub average_crowded_level: f64,
+    pub median_crowded_level: f64,
+    /// `average_crowded_level` with each report weighted by its author's
+    /// reputation score, so a handful of low-trust outliers can't swing the
+    /// headline number the way they can the plain average.
+    pub effective_crowded_level: f64,
+    pub top_missing_goods: Vec<(String, i64)>,
+}
+
+pub async fn location_stats(
+    location_name: String,
+    query: StatsQuery,
+    if_none_match: Option<String>,
+    cache: Cache,
+    db: Db,
+) -> Result<impl Reply, Rejection> {
+    let cache_key = format!("location_stats:{}:{}", location_name, query.window_hours);
+    let etag_key = format!("{}:etag", cache_key);
+    if let (Some(body), Some(etag)) = (cache.get(&cache_key), cache.get(&etag_key)) {
+        let reply = warp::reply::with_header(body, "Content-Type", "application/json");
+        return Ok(crate::caching::with_etag(&etag, if_none_match, reply));
+    }
+
+    let name_for_query = location_name.clone();
+    let (rows, weights): (Vec<Checkin>, HashMap<String, f64>) = db
+        .run(move |conn| {
+            use crate::schema::checkins::dsl;
+            let since = Utc::now().naive_utc() - Duration::hours(query.window_hours);
+            let rows: Vec<Checkin> = dsl::checkins
+                .filter(dsl::location_name.eq(name_for_query))
+                .filter(dsl::observed_at.ge(since))
+                .filter(dsl::hidden.eq(false))
+                .load(conn)?;
+
+            let user_ids: Vec<String> = rows.iter().map(|r| r.user_id.clone()).collect();
+            let weights = crate::reputation::scores_for(conn, &user_ids)?;
+            Ok((rows, weights))
+        })
+        .await
+        .map_err(ApiError::from)
+        .map_err(warp::reject::custom)?;
+
+    if rows.is_empty() {
+        return Err(warp::reject::custom(ApiError::NotFound));
+    }
+
+    let max_created_at = rows.iter().map(|r| r.created_at).max();
+    let etag = crate::caching::weak_etag(rows.len() as i64, max_created_at);
+    let body = serde_json::to_string(&summarize(location_name, &rows, &weights))
+        .expect("serialize location stats");
+    cache.put(cache_key, body.clone());
+    cache.put(etag_key, etag.clone());
+
+    let reply = warp::reply::with_header(body, "Content-Type", "application/json");
+    Ok(crate::caching::with_etag(&etag, if_none_match, reply))
+}
+
+fn summarize(location_name: String, rows: &[Checkin], weights: &HashMap<String, f64>) -> LocationStats {
+    let checkin_count = rows.len() as i64;
+
+    let mut levels: Vec<i32> = rows.iter().map(|r| r.crowded_level).collect();
+    let average_crowded_level = levels.iter().sum::<i32>() as f64 / checkin_count as f64;
+
+    let weighted_sum: f64 = rows
+        .iter()
+        .map(|r| {
+            let weight = weights
+                .get(&r.user_id)
+                .copied()
+                .unwrap_or(crate::reputation::DEFAULT_SCORE);
+            r.crowded_level as f64 * weight
+        })
+        .sum();
+    let total_weight: f64 = rows
+        .iter()
+        .map(|r| {
+            weights
+                .get(&r.user_id)
+                .copied()
+                .unwrap_or(crate::reputation::DEFAULT_SCORE)
+        })
+        .sum();
+    let effective_crowded_level = weighted_sum / total_weight;
+
+    levels.sort_unstable();
+    let mid = levels.len() / 2;
+    let median_crowded_level = if levels.len() % 2 == 0 {
+        (levels[mid - 1] + levels[mid]) as f64 / 2.0
+    } else {
+        levels[mid] as f64
+    };
+
+    let mut good_counts: HashMap<&str, i64> = HashMap::new();
+    for row in rows {
+        for good in &row.missing_goods {
+            *good_counts.entry(good.as_str()).or_insert(0) += 1;
+        }
+    }
+    let mut top_missing_goods: Vec<(String, i64)> = good_counts
+        .into_iter()
+        .map(|(good, count)| (good.to_string(), count))
+        .collect();
+    top_missing_goods.sort_by(|a, b| b.1.cmp(&a.1));
+    top_missing_goods.truncate(5);
+
+    LocationStats {
+        location_name,
+        checkin_count,
+        average_crowded_level,
+        median_crowded_level,
+        effective_crowded_level,
+        top_missing_goods,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use diesel_geography::types::GeogPoint;
+
+    fn checkin(crowded_level: i32, missing_goods: Vec<&str>) -> Checkin {
+        Checkin {
+            id: 0,
+            gps: GeogPoint {
+                x: 0.0,
+                y: 0.0,
+                srid: None,
+            },
+            location_name: "Demo Market".to_string(),
+            crowded_level,
+            missing_goods: missing_goods.into_iter().map(String::from).collect(),
+            user_id: "u".to_string(),
+            client_id: "c".to_string(),
+            created_at: Utc::now().naive_utc(),
+            location_id: None,
+            updated_at: Utc::now().naive_utc(),
+            hidden: false,
+            note: None,
+            observed_at: Utc::now().naive_utc(),
+            idempotency_key: None,
+            geocoded_address: None,
+            region: "u0".to_string(),
+        }
+    }
+
+    #[test]
+    fn summarize_computes_average_median_and_top_goods() {
+        let rows = vec![
+            checkin(1, vec!["flour"]),
+            checkin(3, vec!["flour", "rice"]),
+            checkin(5, vec!["rice"]),
+        ];
+        let stats = summarize("Demo Market".to_string(), &rows, &HashMap::new());
+        assert_eq!(stats.checkin_count, 3);
+        assert!((stats.average_crowded_level - 3.0).abs() < f64::EPSILON);
+        assert!((stats.median_crowded_level - 3.0).abs() < f64::EPSILON);
+        assert_eq!(stats.top_missing_goods[0].1, 2);
+        // All rows share a user with no recorded reputation yet, so every
+        // weight falls back to the default and the weighted average matches
+        // the plain one.
+        assert!((stats.effective_crowded_level - 3.0).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn summarize_weights_by_reputation() {
+        let rows = vec![checkin_for("trusted", 5), checkin_for("untrusted", 1)];
+        let mut weights = HashMap::new();
+        weights.insert("trusted".to_string(), 2.0);
+        weights.insert("untrusted".to_string(), 0.5);
+
+        let stats = summarize("Demo Market".to_string(), &rows, &weights);
+        // (5*2.0 + 1*0.5) / (2.0 + 0.5) = 4.2, pulled toward the trusted
+        // user's report rather than splitting the difference at 3.0.
+        assert!((stats.effective_crowded_level - 4.2).abs() < 1e-9);
+    }
+
+    fn checkin_for(user_id: &str, crowded_level: i32) -> Checkin {
+        let mut c = checkin(crowded_level, vec![]);
+        c.user_id = user_id.to_string();
+        c
+    }
+}
@@ -0,0 +1,156 @@
+//! `GET /v1/locations/:id/status`: a "right now" status derived from recent
+//! checkins with exponential time-decay weighting, rather than the plain
+//! all-time average clients compute from `GET /v1/locations/:id/forecast`
+//! data today -- a crowded report from six hours ago shouldn't carry the
+//! same weight as one from five minutes ago.
+
+use crate::db::Db;
+use crate::errors::ApiError;
+use crate::model::Checkin;
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel::prelude::*;
+use serde::Serialize;
+use warp::{Rejection, Reply};
+
+/// Checkins older than this are ignored entirely, not just down-weighted --
+/// a report from yesterday says nothing about right now.
+const STATUS_WINDOW_MINUTES: i64 = 180;
+/// How fast a report's influence decays: half its weight every this many
+/// minutes.
+const DECAY_HALF_LIFE_MINUTES: f64 = 30.0;
+/// Decayed report weight needed to call the status fully confident.
+const CONFIDENT_WEIGHT: f64 = 3.0;
+
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ShopStatus {
+    Quiet,
+    Moderate,
+    Crowded,
+    Unknown,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LocationStatusResponse {
+    pub status: ShopStatus,
+    /// 0.0 (no recent reports) to 1.0 (plenty of recent, fresh reports).
+    pub confidence: f64,
+    pub report_count: i64,
+    pub weighted_crowded_level: Option<f64>,
+}
+
+pub async fn location_status(location_id: i32, db: Db) -> Result<impl Reply, Rejection> {
+    let rows: Vec<Checkin> = db
+        .run(move |conn| {
+            use crate::schema::checkins::dsl;
+            let since = Utc::now().naive_utc() - Duration::minutes(STATUS_WINDOW_MINUTES);
+            dsl::checkins
+                .filter(dsl::location_id.eq(location_id))
+                .filter(dsl::observed_at.ge(since))
+                .filter(dsl::hidden.eq(false))
+                .load(conn)
+        })
+        .await
+        .map_err(ApiError::from)
+        .map_err(warp::reject::custom)?;
+
+    Ok(warp::reply::json(&compute_status(&rows, Utc::now().naive_utc())))
+}
+
+fn decay_weight(observed_at: NaiveDateTime, now: NaiveDateTime) -> f64 {
+    let age_minutes = (now - observed_at).num_seconds() as f64 / 60.0;
+    0.5f64.powf(age_minutes.max(0.0) / DECAY_HALF_LIFE_MINUTES)
+}
+
+fn compute_status(rows: &[Checkin], now: NaiveDateTime) -> LocationStatusResponse {
+    let mut weighted_sum = 0.0;
+    let mut weight_total = 0.0;
+    for row in rows {
+        let weight = decay_weight(row.observed_at, now);
+        weighted_sum += row.crowded_level as f64 * weight;
+        weight_total += weight;
+    }
+
+    if weight_total <= 0.0 {
+        return LocationStatusResponse {
+            status: ShopStatus::Unknown,
+            confidence: 0.0,
+            report_count: 0,
+            weighted_crowded_level: None,
+        };
+    }
+
+    let weighted_crowded_level = weighted_sum / weight_total;
+    let status = if weighted_crowded_level <= 1.5 {
+        ShopStatus::Quiet
+    } else if weighted_crowded_level <= 3.5 {
+        ShopStatus::Moderate
+    } else {
+        ShopStatus::Crowded
+    };
+
+    LocationStatusResponse {
+        status,
+        confidence: (weight_total / CONFIDENT_WEIGHT).min(1.0),
+        report_count: rows.len() as i64,
+        weighted_crowded_level: Some(weighted_crowded_level),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use diesel_geography::types::GeogPoint;
+
+    fn checkin_at(observed_at: NaiveDateTime, crowded_level: i32) -> Checkin {
+        Checkin {
+            id: 0,
+            gps: GeogPoint {
+                x: 0.0,
+                y: 0.0,
+                srid: None,
+            },
+            location_name: "Demo Market".to_string(),
+            crowded_level,
+            missing_goods: vec![],
+            user_id: "u".to_string(),
+            client_id: "c".to_string(),
+            created_at: observed_at,
+            location_id: Some(1),
+            updated_at: observed_at,
+            hidden: false,
+            note: None,
+            observed_at,
+            idempotency_key: None,
+            geocoded_address: None,
+            region: "u0".to_string(),
+        }
+    }
+
+    #[test]
+    fn no_recent_reports_is_unknown() {
+        let status = compute_status(&[], Utc::now().naive_utc());
+        assert_eq!(status.status, ShopStatus::Unknown);
+        assert_eq!(status.confidence, 0.0);
+    }
+
+    #[test]
+    fn recent_reports_outweigh_stale_ones() {
+        let now = Utc::now().naive_utc();
+        let rows = vec![
+            checkin_at(now - Duration::minutes(120), 5), // stale, heavily decayed
+            checkin_at(now - Duration::minutes(1), 1),   // fresh
+        ];
+        let status = compute_status(&rows, now);
+        assert_eq!(status.status, ShopStatus::Quiet);
+        assert_eq!(status.report_count, 2);
+    }
+
+    #[test]
+    fn few_reports_yield_low_confidence() {
+        let now = Utc::now().naive_utc();
+        let rows = vec![checkin_at(now, 3)];
+        let status = compute_status(&rows, now);
+        assert!(status.confidence < 1.0);
+    }
+}
@@ -0,0 +1,101 @@
+//! Geohash-based region routing for the `checkins` table. `checkins.region`
+//! (see the `add_checkin_region` migration) is a generated column Postgres
+//! computes from `gps` on every insert, so every row is already tagged with
+//! its shard key for free — this module just lets query code compute the
+//! same value for a point so it can narrow down to one region *before*
+//! paying for an `ST_DWithin` scan.
+//!
+//! This intentionally stops short of physically partitioning the table: the
+//! checkins primary key is a bare `id`, and dozens of call sites rely on
+//! `checkins.find(id)` to work without also knowing a row's region. Declarative
+//! partitioning in Postgres requires the partition key in every unique
+//! constraint, which would force that primary key to become `(id, region)`
+//! and touch every one of those call sites. The indexed `region` column gets
+//! us the "only touch nearby rows" win without that rewrite; moving to real
+//! partitions is a follow-up once `region` has proven itself as the shard key.
+
+/// Characters are cut to this length, matching the `substring(... for 4)`
+/// truncation the `add_checkin_region` migration applies to
+/// `ST_GeoHash(gps::geometry, 5)` — keep these two in sync.
+const REGION_PRECISION: usize = 4;
+
+/// Prefix length used to pre-filter hot queries (see [`shard_prefix_for`]).
+/// Deliberately much coarser than `REGION_PRECISION`: a 2-character geohash
+/// cell is roughly 1,250km x 625km, far wider than any `radius` a "nearby"
+/// query is realistically called with, so narrowing to it can't silently
+/// drop a true match near a cell edge the way matching on the full 4-char
+/// `region` column could.
+const SHARD_PREFIX_LEN: usize = 2;
+
+const BASE32: &[u8] = b"0123456789bcdefghjkmnpqrstuvwxyz";
+
+/// The region a `(lat, lng)` point falls into, computed the same way the
+/// `checkins.region` column is: a `REGION_PRECISION`-character geohash
+/// prefix.
+pub fn region_for(lat: f64, lng: f64) -> String {
+    geohash(lat, lng, REGION_PRECISION)
+}
+
+/// Coarse geohash prefix for `(lat, lng)`, suitable for pre-filtering a
+/// `checkins` query down to the `region`s it could possibly match (via
+/// `region LIKE 'prefix%'`) before the real distance check runs.
+pub fn shard_prefix_for(lat: f64, lng: f64) -> String {
+    geohash(lat, lng, SHARD_PREFIX_LEN)
+}
+
+fn geohash(lat: f64, lng: f64, precision: usize) -> String {
+    let mut lat_range = (-90.0_f64, 90.0_f64);
+    let mut lng_range = (-180.0_f64, 180.0_f64);
+    let mut geohash = String::with_capacity(precision);
+    let mut bit = 0;
+    let mut ch = 0usize;
+    let mut even_bit = true;
+
+    while geohash.len() < precision {
+        if even_bit {
+            let mid = (lng_range.0 + lng_range.1) / 2.0;
+            if lng >= mid {
+                ch |= 1 << (4 - bit);
+                lng_range.0 = mid;
+            } else {
+                lng_range.1 = mid;
+            }
+        } else {
+            let mid = (lat_range.0 + lat_range.1) / 2.0;
+            if lat >= mid {
+                ch |= 1 << (4 - bit);
+                lat_range.0 = mid;
+            } else {
+                lat_range.1 = mid;
+            }
+        }
+        even_bit = !even_bit;
+
+        if bit < 4 {
+            bit += 1;
+        } else {
+            geohash.push(BASE32[ch] as char);
+            bit = 0;
+            ch = 0;
+        }
+    }
+
+    geohash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn region_for_is_stable_and_right_length() {
+        let region = region_for(52.5200, 13.4050); // Berlin
+        assert_eq!(region.len(), REGION_PRECISION);
+        assert_eq!(region, region_for(52.5200, 13.4050));
+    }
+
+    #[test]
+    fn region_for_separates_distant_points() {
+        assert_ne!(region_for(52.5200, 13.4050), region_for(-33.8688, 151.2093));
+    }
+}
@@ -0,0 +1,141 @@
+//! Chunked-JSON streaming for large result sets (GeoJSON `FeatureCollection`s,
+//! cluster summaries), so a city-wide query never buffers its full response in
+//! memory. Pages are pulled lazily as the client reads the body; if the client
+//! disconnects, `hyper` simply stops polling the stream and the remaining pages
+//! are never fetched.
+
+use bytes::Bytes;
+use futures::future::BoxFuture;
+use futures::stream::{self, Stream};
+use std::io;
+
+pub type PageResult = io::Result<Vec<(i32, String)>>;
+
+const PAGE_SIZE: i64 = 500;
+/// Upper bound on rows served per request.
+pub const MAX_ROWS: i64 = 50_000;
+
+enum Phase {
+    Preamble,
+    Rows,
+    Closing,
+    Done,
+}
+
+struct State<F> {
+    phase: Phase,
+    last_id: Option<i32>,
+    rows_sent: i64,
+    first_row: bool,
+    fetch_page: F,
+}
+
+/// Streams `{"type":"FeatureCollection","<key>":[` then comma-separated JSON
+/// values produced by `fetch_page`, then `]}`. `fetch_page(after_id, limit)`
+/// runs the next keyset-paginated query on the blocking pool and returns
+/// `(cursor, json)` pairs to resume after; an empty page ends the stream
+/// normally. A query error truncates the stream (no closing bracket) rather
+/// than panicking.
+pub fn json_feature_stream<F>(
+    key: &'static str,
+    fetch_page: F,
+) -> impl Stream<Item = io::Result<Bytes>>
+where
+    F: FnMut(Option<i32>, i64) -> BoxFuture<'static, PageResult> + Send + 'static,
+{
+    let state = State {
+        phase: Phase::Preamble,
+        last_id: None,
+        rows_sent: 0,
+        first_row: true,
+        fetch_page,
+    };
+
+    stream::unfold(state, move |mut state| async move {
+        match state.phase {
+            Phase::Preamble => {
+                state.phase = Phase::Rows;
+                let preamble = format!(r#"{{"type":"FeatureCollection","{}":["#, key);
+                Some((Ok(Bytes::from(preamble)), state))
+            }
+            Phase::Rows if state.rows_sent >= MAX_ROWS => {
+                state.phase = Phase::Closing;
+                Some((Ok(Bytes::new()), state))
+            }
+            Phase::Rows => {
+                let limit = PAGE_SIZE.min(MAX_ROWS - state.rows_sent);
+                match (state.fetch_page)(state.last_id, limit).await {
+                    Ok(page) if page.is_empty() => {
+                        state.phase = Phase::Closing;
+                        Some((Ok(Bytes::new()), state))
+                    }
+                    Ok(page) => {
+                        state.rows_sent += page.len() as i64;
+                        let mut chunk = String::new();
+                        for (cursor, json) in page.into_iter() {
+                            if !state.first_row {
+                                chunk.push(',');
+                            }
+                            state.first_row = false;
+                            chunk.push_str(&json);
+                            state.last_id = Some(cursor);
+                        }
+                        Some((Ok(Bytes::from(chunk)), state))
+                    }
+                    Err(e) => {
+                        error!("streaming query failed, truncating response: {}", e);
+                        None
+                    }
+                }
+            }
+            Phase::Closing => {
+                state.phase = Phase::Done;
+                Some((Ok(Bytes::from("]}")), state))
+            }
+            Phase::Done => None,
+        }
+    })
+}
+
+struct LineState<F> {
+    last_id: Option<i32>,
+    rows_sent: i64,
+    fetch_page: F,
+}
+
+/// Streams page rows verbatim (already newline-terminated by the caller),
+/// for line-oriented formats like CSV and NDJSON. Shares `json_feature_stream`'s
+/// keyset-paging shape but skips the JSON envelope.
+pub fn line_stream<F>(fetch_page: F) -> impl Stream<Item = io::Result<Bytes>>
+where
+    F: FnMut(Option<i32>, i64) -> BoxFuture<'static, PageResult> + Send + 'static,
+{
+    let state = LineState {
+        last_id: None,
+        rows_sent: 0,
+        fetch_page,
+    };
+
+    stream::unfold(state, move |mut state| async move {
+        if state.rows_sent >= MAX_ROWS {
+            return None;
+        }
+        let limit = PAGE_SIZE.min(MAX_ROWS - state.rows_sent);
+        match (state.fetch_page)(state.last_id, limit).await {
+            Ok(page) if page.is_empty() => None,
+            Ok(page) => {
+                state.rows_sent += page.len() as i64;
+                let mut chunk = String::new();
+                for (cursor, line) in page.into_iter() {
+                    chunk.push_str(&line);
+                    state.last_id = Some(cursor);
+                }
+                Some((Ok(Bytes::from(chunk)), state))
+            }
+            Err(e) => {
+                error!("streaming export query failed, truncating response: {}", e);
+                None
+            }
+        }
+    })
+}
@@ -0,0 +1,111 @@
+//! Incremental sync for offline-first clients: `GET /v1/sync?since=<cursor>`
+//! returns every checkin created or edited after `since`, plus tombstones
+//! (see [`crate::model::CheckinTombstone`]) for anything hard-deleted since
+//! then, so a client that missed a deletion notification can still drop it
+//! from its local cache. The cursor is just the latest `updated_at` /
+//! `deleted_at` seen, opaque to the client beyond "pass it back next time".
+
+use crate::db::Db;
+use crate::errors::ApiError;
+use crate::model::{Checkin, CheckinTombstone};
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use warp::{Rejection, Reply};
+
+/// Caps a single page the same way the other list endpoints do; a client
+/// that's further behind than this just calls again with the returned
+/// cursor to fetch the next page.
+const SYNC_PAGE_SIZE: i64 = 500;
+
+#[derive(Debug, Deserialize)]
+pub struct SyncQuery {
+    pub since: NaiveDateTime,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SyncResponse {
+    pub changes: Vec<Checkin>,
+    pub tombstones: Vec<CheckinTombstone>,
+    pub cursor: NaiveDateTime,
+    /// True if there are more changes than fit in this page -- call again
+    /// with `cursor` as the new `since` to keep draining.
+    pub has_more: bool,
+}
+
+pub async fn sync(query: SyncQuery, db: Db) -> Result<impl Reply, Rejection> {
+    let since = query.since;
+    let (changes, tombstones): (Vec<Checkin>, Vec<CheckinTombstone>) = db
+        .run(move |conn| {
+            use crate::schema::checkin_tombstones::dsl as tombstones_dsl;
+            use crate::schema::checkins::dsl as checkins_dsl;
+
+            let changes: Vec<Checkin> = checkins_dsl::checkins
+                .filter(checkins_dsl::updated_at.gt(since))
+                .order(checkins_dsl::updated_at.asc())
+                .limit(SYNC_PAGE_SIZE)
+                .load(conn)?;
+
+            let tombstones: Vec<CheckinTombstone> = tombstones_dsl::checkin_tombstones
+                .filter(tombstones_dsl::deleted_at.gt(since))
+                .order(tombstones_dsl::deleted_at.asc())
+                .limit(SYNC_PAGE_SIZE)
+                .load(conn)?;
+
+            Ok((changes, tombstones))
+        })
+        .await
+        .map_err(ApiError::from)
+        .map_err(warp::reject::custom)?;
+
+    // `changes` and `tombstones` are paged independently, so if one of them
+    // came back full it may be hiding rows that fall *before* the other
+    // stream's last-included timestamp. Advancing the cursor past those
+    // hidden rows would skip them forever, since the next call's `since` is
+    // already past them. Clamp the cursor (and both result sets) to the
+    // smaller of the two truncated streams' last-included timestamps so the
+    // next page is guaranteed to pick up everything in between.
+    let changes_cutoff = if changes.len() as i64 == SYNC_PAGE_SIZE {
+        changes.last().map(|c| c.updated_at)
+    } else {
+        None
+    };
+    let tombstones_cutoff = if tombstones.len() as i64 == SYNC_PAGE_SIZE {
+        tombstones.last().map(|t| t.deleted_at)
+    } else {
+        None
+    };
+
+    let (changes, tombstones, cursor, has_more) = match (changes_cutoff, tombstones_cutoff) {
+        (None, None) => {
+            let cursor = changes
+                .iter()
+                .map(|c| c.updated_at)
+                .chain(tombstones.iter().map(|t| t.deleted_at))
+                .max()
+                .unwrap_or(since);
+            (changes, tombstones, cursor, false)
+        }
+        (a, b) => {
+            let cutoff = match (a, b) {
+                (Some(a), Some(b)) => a.min(b),
+                (Some(a), None) => a,
+                (None, Some(b)) => b,
+                (None, None) => unreachable!(),
+            };
+            let changes: Vec<Checkin> = changes.into_iter().filter(|c| c.updated_at <= cutoff).collect();
+            let tombstones: Vec<CheckinTombstone> = tombstones
+                .into_iter()
+                .filter(|t| t.deleted_at <= cutoff)
+                .collect();
+            (changes, tombstones, cutoff, true)
+        }
+    };
+
+    Ok(warp::reply::json(&SyncResponse {
+        changes,
+        tombstones,
+        cursor,
+        has_more,
+    }))
+}
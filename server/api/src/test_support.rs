@@ -0,0 +1,92 @@
+//! Shared harness for integration tests that exercise the warp filters
+//! against a real Postgres connection. Each test gets a single-connection
+//! pool wrapped in `begin_test_transaction`, so nothing a test writes is
+//! ever committed — the transaction (and the one connection it lives on) is
+//! simply dropped at the end of the test, instead of every prior
+//! `#[tokio::test]` leaving its rows behind in `DATABASE_URL`.
+//!
+//! Set `TEST_DATABASE_URL` to point this at a database you don't mind
+//! sharing with your development `DATABASE_URL`; it falls back to
+//! `DATABASE_URL` if unset.
+
+use crate::model::NewJsonCheckin;
+use crate::Pool;
+use diesel::prelude::*;
+use diesel::r2d2::{ConnectionManager, Pool as R2d2Pool};
+use diesel::PgConnection;
+
+pub const TEST_API_KEY: &str = "test-api-key";
+pub const TEST_CLIENT_ID: &str = "some client";
+
+/// A pool backed by a single connection, already inside a transaction that
+/// will never commit. `max_size(1)` is what makes this work: every
+/// `Db::run` call during the test checks the same connection back out of
+/// the pool, so every query sees the same open transaction.
+pub fn test_pool() -> Pool {
+    dotenv::dotenv().ok();
+    let database_url = std::env::var("TEST_DATABASE_URL")
+        .or_else(|_| std::env::var("DATABASE_URL"))
+        .expect("set TEST_DATABASE_URL or DATABASE_URL to run integration tests");
+    let cm = ConnectionManager::<PgConnection>::new(database_url);
+    let pool = R2d2Pool::builder()
+        .max_size(1)
+        .build(cm)
+        .expect("build test connection pool");
+
+    let conn = pool.get().expect("get test connection");
+    conn.begin_test_transaction()
+        .expect("begin test transaction");
+    ensure_test_client(&conn);
+    drop(conn);
+
+    pool
+}
+
+/// Registers [`TEST_API_KEY`] for [`TEST_CLIENT_ID`] inside the test
+/// transaction, tolerating a rerun against a database that already has it.
+fn ensure_test_client(conn: &PgConnection) {
+    use crate::schema::clients::dsl::*;
+    diesel::insert_into(clients)
+        .values((client_id.eq(TEST_CLIENT_ID), api_key.eq(TEST_API_KEY)))
+        .on_conflict(client_id)
+        .do_update()
+        .set(api_key.eq(TEST_API_KEY))
+        .execute(conn)
+        .expect("seed test client");
+}
+
+/// A minimal, valid checkin body. Override fields on the result for
+/// scenario-specific tests.
+pub fn checkin_json() -> NewJsonCheckin {
+    NewJsonCheckin {
+        gps: [53.55, 9.97],
+        location_name: "some location".to_string(),
+        crowded_level: 3,
+        user_id: "some user".to_string(),
+        client_id: TEST_CLIENT_ID.to_string(),
+        missing_goods: vec![String::from("flour")],
+        note: None,
+        observed_at: None,
+    }
+}
+
+/// The full `/v1` checkin API, wired up against `pool` with default config
+/// (no geocoder, no push provider) — enough for the handler paths covered
+/// by the integration tests in [`crate::tests`].
+pub fn api(pool: Pool) -> impl warp::Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    let config = crate::config::Config::from_env();
+    let blob_store: crate::blobs::SharedBlobStore =
+        std::sync::Arc::new(crate::blobs::FsBlobStore::new(config.photo_storage_dir.clone()));
+    crate::filters::checkins(
+        crate::db::Db::new(pool),
+        config.body_limit_bytes,
+        config.checkin_rate_limit_per_minute,
+        crate::ws::Registry::new(),
+        crate::cache::Cache::new(60),
+        None,
+        None,
+        blob_store,
+        config.photo_limit_bytes,
+        None,
+    )
+}
@@ -0,0 +1,85 @@
+//! `/v2/checkins` accepts a richer payload than the original `/v1` contract —
+//! a nested `location` object instead of a flat `[lat, lng]` pair, and a
+//! `client_timestamp` that maps onto [`NewJsonCheckin::observed_at`] — but
+//! converts straight into [`NewJsonCheckin`] and hands off to
+//! [`handlers::create_checkin`], so validation, the ban check, cache
+//! invalidation and the websocket broadcast stay in one place instead of
+//! forking per version.
+
+use crate::auth::AuthenticatedClient;
+use crate::cache::Cache;
+use crate::db::Db;
+use crate::handlers;
+use crate::model::NewJsonCheckin;
+use crate::pseudonymize::SharedPseudonymizer;
+use crate::ws;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use warp::{Rejection, Reply};
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct LocationV2 {
+    pub lat: f64,
+    pub lng: f64,
+    pub name: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct NewJsonCheckinV2 {
+    pub location: LocationV2,
+    pub crowded_level: i32,
+    pub user_id: String,
+    pub client_id: String,
+    #[serde(default)]
+    pub missing_goods: Vec<String>,
+    #[serde(default)]
+    pub note: Option<String>,
+    /// When the client observed the crowding, not when the server received
+    /// the report. Maps onto `NewJsonCheckin::observed_at`.
+    #[serde(default)]
+    pub client_timestamp: Option<DateTime<Utc>>,
+}
+
+impl From<NewJsonCheckinV2> for NewJsonCheckin {
+    fn from(checkin: NewJsonCheckinV2) -> NewJsonCheckin {
+        NewJsonCheckin {
+            gps: [checkin.location.lat, checkin.location.lng],
+            location_name: checkin.location.name,
+            crowded_level: checkin.crowded_level,
+            user_id: checkin.user_id,
+            client_id: checkin.client_id,
+            missing_goods: checkin.missing_goods,
+            note: checkin.note,
+            observed_at: checkin.client_timestamp,
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/v2/checkins",
+    request_body = NewJsonCheckinV2,
+    responses(
+        (status = 201, description = "Checkin created"),
+        (status = 422, description = "Validation error"),
+        (status = 429, description = "Rate limited")
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn create_checkin(
+    request_id: String,
+    client: AuthenticatedClient,
+    payload: NewJsonCheckinV2,
+    db: Db,
+    ws_registry: ws::Registry,
+    cache: Cache,
+    pseudonymizer: SharedPseudonymizer,
+) -> Result<impl Reply, Rejection> {
+    handlers::create_checkin(
+        request_id, client, None, payload.into(), db, ws_registry, cache, None, None, pseudonymizer,
+    )
+    .await
+}
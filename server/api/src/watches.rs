@@ -0,0 +1,136 @@
+//! Saved-place alerts: a user registers a point, radius and list of goods
+//! they care about, and gets a push notification when a new checkin inside
+//! that radius reports one of those goods missing. Delivery is driven
+//! directly off `create_checkin` (see `spawn_watch_notifications`) rather
+//! than polled like [`crate::webhooks`], since "a matching checkin just
+//! happened" is an event, not a rolling aggregate to sample periodically.
+
+use crate::db::Db;
+use crate::errors::ApiError;
+use crate::model::{Checkin, NewWatch, NewWatchJson, Watch};
+use crate::push::PushProvider;
+use crate::schema::watches;
+use diesel::dsl::sql_query;
+use diesel::prelude::*;
+use diesel::sql_types::{Array, Int4, Text};
+use diesel_geography::types::GeogPoint;
+use std::sync::Arc;
+use warp::http::StatusCode;
+use warp::{Rejection, Reply};
+
+pub async fn list_watches(user_id: String, db: Db) -> Result<impl Reply, Rejection> {
+    let results: Vec<Watch> = db
+        .run(move |conn| {
+            watches::table
+                .filter(watches::user_id.eq(user_id))
+                .order(watches::id.asc())
+                .load(conn)
+        })
+        .await
+        .map_err(ApiError::from)
+        .map_err(warp::reject::custom)?;
+
+    Ok(warp::reply::json(&results))
+}
+
+pub async fn create_watch(
+    user_id: String,
+    client: crate::auth::AuthenticatedClient,
+    body: NewWatchJson,
+    db: Db,
+) -> Result<impl Reply, Rejection> {
+    let new_watch = NewWatch {
+        user_id,
+        client_id: client.client_id,
+        gps: GeogPoint {
+            x: body.lat,
+            y: body.lng,
+            srid: None,
+        },
+        radius_meters: body.radius_meters,
+        goods: body.goods,
+        push_token: body.push_token,
+    };
+
+    let created: Watch = db
+        .run(move |conn| {
+            diesel::insert_into(watches::table)
+                .values(&new_watch)
+                .get_result(conn)
+        })
+        .await
+        .map_err(ApiError::from)
+        .map_err(warp::reject::custom)?;
+
+    Ok(warp::reply::with_status(
+        warp::reply::json(&created),
+        StatusCode::CREATED,
+    ))
+}
+
+pub async fn delete_watch(
+    user_id: String,
+    watch_id: i32,
+    db: Db,
+) -> Result<impl Reply, Rejection> {
+    db.run(move |conn| {
+        diesel::delete(
+            watches::table
+                .filter(watches::id.eq(watch_id))
+                .filter(watches::user_id.eq(user_id)),
+        )
+        .execute(conn)
+    })
+    .await
+    .map_err(ApiError::from)
+    .map_err(warp::reject::custom)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Pushes `checkin` to every watch it falls inside the radius of and that
+/// lists one of its `missing_goods`. Detached from the request/response
+/// cycle on purpose: a slow or down push gateway must never delay the
+/// client's 201, and a failed delivery just leaves that watch un-notified
+/// for this checkin rather than failing the checkin itself.
+pub fn spawn_watch_notifications(push_provider: Arc<dyn PushProvider>, db: Db, checkin: Checkin) {
+    tokio::task::spawn(async move {
+        let checkin_id = checkin.id;
+        let missing_goods = checkin.missing_goods.clone();
+        let location_name = checkin.location_name.clone();
+        let matches = db
+            .run(move |conn| {
+                sql_query(
+                    "select * from watches \
+                     where goods && $1 \
+                     and ST_DWithin(gps, (select gps from checkins where id = $2), radius_meters, false)",
+                )
+                .bind::<Array<Text>, _>(missing_goods.clone())
+                .bind::<Int4, _>(checkin_id)
+                .get_results::<Watch>(conn)
+            })
+            .await;
+
+        let matches = match matches {
+            Ok(matches) => matches,
+            Err(e) => {
+                error!(checkin_id, "failed to match watches for checkin: {}", e);
+                return;
+            }
+        };
+
+        for watch in matches {
+            let title = "Shortage reported near a saved place";
+            let body = format!(
+                "{} is now reported missing at {}",
+                missing_goods.join(", "),
+                location_name
+            );
+            if let Err(e) = push_provider.send(&watch.push_token, title, &body).await {
+                error!(watch_id = watch.id, checkin_id, "push delivery failed: {}", e);
+            } else {
+                info!(watch_id = watch.id, checkin_id, "delivered watch notification");
+            }
+        }
+    });
+}
@@ -0,0 +1,236 @@
+//! Crowding alert webhooks: operators register a URL for one of their
+//! locations, and a background task POSTs a signed JSON payload whenever
+//! that location's rolling average `crowded_level` crosses the registered
+//! threshold. CRUD lives here rather than in `admin.rs` since the polling
+//! task and delivery logic need the same model types; `admin::routes` just
+//! nests `webhooks::routes` under its existing `/admin` prefix and auth gate.
+
+use crate::db::Db;
+use crate::errors::ApiError;
+use crate::model::{NewWebhook, NewWebhookJson, Webhook};
+use crate::schema::webhooks;
+use diesel::prelude::*;
+use diesel::sql_types::{Double, Text};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use warp::http::StatusCode;
+use warp::{Filter, Rejection, Reply};
+
+/// How often the background task re-checks rolling averages against
+/// registered thresholds.
+const POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5 * 60);
+
+/// Window the rolling average is computed over, matching the "currently
+/// crowded" framing rather than an all-time average.
+const ROLLING_WINDOW_HOURS: i64 = 1;
+
+const DELIVERY_ATTEMPTS: u32 = 3;
+
+pub fn routes(db: Db) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path("webhooks").and(
+        list_webhooks(db.clone())
+            .or(create_webhook(db.clone()))
+            .or(delete_webhook(db)),
+    )
+}
+
+fn list_webhooks(db: Db) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path::end()
+        .and(warp::get())
+        .and(with_db(db))
+        .and_then(list)
+}
+
+async fn list(db: Db) -> Result<impl Reply, Rejection> {
+    let rows: Vec<Webhook> = db
+        .run(|conn| webhooks::table.order(webhooks::id.asc()).load(conn))
+        .await
+        .map_err(ApiError::from)
+        .map_err(warp::reject::custom)?;
+
+    Ok(warp::reply::json(&rows))
+}
+
+fn create_webhook(db: Db) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path::end()
+        .and(warp::post())
+        .and(warp::body::content_length_limit(1024))
+        .and(warp::body::json())
+        .and(with_db(db))
+        .and_then(create)
+}
+
+async fn create(body: NewWebhookJson, db: Db) -> Result<impl Reply, Rejection> {
+    let new_webhook = NewWebhook::from(body);
+    let created: Webhook = db
+        .run(move |conn| {
+            diesel::insert_into(webhooks::table)
+                .values(&new_webhook)
+                .get_result(conn)
+        })
+        .await
+        .map_err(ApiError::from)
+        .map_err(warp::reject::custom)?;
+
+    Ok(warp::reply::with_status(
+        warp::reply::json(&created),
+        StatusCode::CREATED,
+    ))
+}
+
+fn delete_webhook(db: Db) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path::param::<i32>()
+        .and(warp::path::end())
+        .and(warp::delete())
+        .and(with_db(db))
+        .and_then(delete)
+}
+
+async fn delete(webhook_id: i32, db: Db) -> Result<impl Reply, Rejection> {
+    db.run(move |conn| {
+        diesel::delete(webhooks::table.find(webhook_id)).execute(conn)
+    })
+    .await
+    .map_err(ApiError::from)
+    .map_err(warp::reject::custom)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+fn with_db(db: Db) -> impl Filter<Extract = (Db,), Error = std::convert::Infallible> + Clone {
+    warp::any().map(move || db.clone())
+}
+
+#[derive(Debug, QueryableByName)]
+struct LocationAverage {
+    #[sql_type = "Text"]
+    location_name: String,
+    #[sql_type = "Double"]
+    avg_crowded_level: f64,
+}
+
+#[derive(serde::Serialize)]
+struct AlertPayload<'a> {
+    location_name: &'a str,
+    average_crowded_level: f64,
+    threshold: i32,
+    triggered_at: chrono::NaiveDateTime,
+}
+
+/// Spawns the periodic threshold-check task. Runs until the process exits.
+pub fn spawn(db: Db) {
+    tokio::task::spawn(async move {
+        let client = reqwest::Client::new();
+        // Tracks which webhooks are currently above their threshold, so a
+        // sustained crowd only fires one alert instead of one per poll.
+        let above_threshold: Arc<Mutex<HashMap<i32, bool>>> = Arc::new(Mutex::new(HashMap::new()));
+        let mut interval = tokio::time::interval(POLL_INTERVAL);
+        loop {
+            interval.tick().await;
+            if let Err(e) = check_once(&db, &client, &above_threshold).await {
+                error!("webhook threshold check failed: {}", e);
+            }
+        }
+    });
+}
+
+async fn check_once(
+    db: &Db,
+    client: &reqwest::Client,
+    above_threshold: &Arc<Mutex<HashMap<i32, bool>>>,
+) -> Result<(), crate::db::DbError> {
+    let registered: Vec<Webhook> = db.run(|conn| webhooks::table.load(conn)).await?;
+    if registered.is_empty() {
+        return Ok(());
+    }
+
+    let averages: Vec<LocationAverage> = db
+        .run(|conn| {
+            diesel::sql_query(format!(
+                "select location_name, avg(crowded_level) as avg_crowded_level \
+                 from checkins \
+                 where not hidden and observed_at >= now() - interval '{} hours' \
+                 group by location_name",
+                ROLLING_WINDOW_HOURS
+            ))
+            .get_results(conn)
+        })
+        .await?;
+    let averages: HashMap<String, f64> = averages
+        .into_iter()
+        .map(|row| (row.location_name, row.avg_crowded_level))
+        .collect();
+
+    let mut above = above_threshold.lock().await;
+    for webhook in registered {
+        let current_average = averages.get(&webhook.location_name).copied().unwrap_or(0.0);
+        let is_above = current_average >= f64::from(webhook.crowded_level_threshold);
+        let was_above = above.get(&webhook.id).copied().unwrap_or(false);
+        above.insert(webhook.id, is_above);
+
+        if is_above && !was_above {
+            let payload = AlertPayload {
+                location_name: &webhook.location_name,
+                average_crowded_level: current_average,
+                threshold: webhook.crowded_level_threshold,
+                triggered_at: chrono::Utc::now().naive_utc(),
+            };
+            deliver(client, &webhook, &payload).await;
+        }
+    }
+
+    Ok(())
+}
+
+/// Delivers `payload` with up to `DELIVERY_ATTEMPTS` tries and an exponential
+/// backoff between them; failures are logged rather than propagated, since a
+/// single shop's unreachable endpoint shouldn't stop other hooks firing.
+async fn deliver(client: &reqwest::Client, webhook: &Webhook, payload: &AlertPayload<'_>) {
+    let body = serde_json::to_string(payload).expect("serialize webhook payload");
+    let signature = sign(&webhook.secret, &body);
+
+    let mut delay = std::time::Duration::from_secs(1);
+    for attempt in 1..=DELIVERY_ATTEMPTS {
+        let result = client
+            .post(&webhook.url)
+            .header("Content-Type", "application/json")
+            .header("X-Webhook-Signature", &signature)
+            .body(body.clone())
+            .send()
+            .await
+            .and_then(|res| res.error_for_status());
+
+        match result {
+            Ok(_) => {
+                info!(webhook_id = webhook.id, attempt, "delivered webhook alert");
+                return;
+            }
+            Err(e) if attempt < DELIVERY_ATTEMPTS => {
+                warn!(
+                    webhook_id = webhook.id,
+                    attempt, "webhook delivery failed, retrying: {}", e
+                );
+                tokio::time::delay_for(delay).await;
+                delay *= 2;
+            }
+            Err(e) => {
+                error!(webhook_id = webhook.id, attempt, "webhook delivery failed, giving up: {}", e);
+            }
+        }
+    }
+}
+
+/// Hex-encoded HMAC-SHA256 of `body` under `secret`, so the receiving
+/// endpoint can verify the alert actually came from us.
+fn sign(secret: &str, body: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.input(body.as_bytes());
+    mac.result()
+        .code()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
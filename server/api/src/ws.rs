@@ -0,0 +1,109 @@
+//! `GET /v1/ws`: clients open a WebSocket, send one JSON subscription message
+//! with a bounding box and a crowding threshold, and then receive every new
+//! checkin that falls inside it as it's created.
+
+use crate::geojson;
+use crate::model::Checkin;
+use futures::{SinkExt, StreamExt};
+use serde::Deserialize;
+use std::sync::Arc;
+use tokio::sync::{mpsc, Mutex};
+use warp::ws::{Message, WebSocket};
+use warp::{Filter, Rejection, Reply};
+
+#[derive(Debug, Deserialize)]
+struct Subscribe {
+    /// `[min_lng, min_lat, max_lng, max_lat]`.
+    bbox: [f64; 4],
+    #[serde(default)]
+    min_crowded_level: i32,
+}
+
+struct Subscription {
+    bbox: [f64; 4],
+    min_crowded_level: i32,
+    tx: mpsc::UnboundedSender<Message>,
+}
+
+/// Shared subscriber list, handed to both the `ws` filter and `create_checkin`.
+#[derive(Clone)]
+pub struct Registry {
+    subscriptions: Arc<Mutex<Vec<Subscription>>>,
+}
+
+impl Registry {
+    pub fn new() -> Self {
+        Registry {
+            subscriptions: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Sends `checkin` to every subscription whose bbox contains it and
+    /// whose threshold it meets, dropping subscriptions whose socket closed.
+    pub async fn broadcast(&self, checkin: &Checkin) {
+        let feature = geojson::checkin_feature(checkin).to_string();
+        let mut subscriptions = self.subscriptions.lock().await;
+        subscriptions.retain(|sub| {
+            if checkin.crowded_level < sub.min_crowded_level || !in_bbox(sub.bbox, checkin) {
+                return true;
+            }
+            sub.tx.send(Message::text(feature.clone())).is_ok()
+        });
+    }
+
+    async fn add(&self, subscription: Subscription) {
+        self.subscriptions.lock().await.push(subscription);
+    }
+}
+
+fn in_bbox(bbox: [f64; 4], checkin: &Checkin) -> bool {
+    let [min_lng, min_lat, max_lng, max_lat] = bbox;
+    checkin.gps.x >= min_lng
+        && checkin.gps.x <= max_lng
+        && checkin.gps.y >= min_lat
+        && checkin.gps.y <= max_lat
+}
+
+pub fn routes(registry: Registry) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path!("v1" / "ws")
+        .and(warp::ws())
+        .and(warp::any().map(move || registry.clone()))
+        .map(|ws: warp::ws::Ws, registry: Registry| {
+            ws.on_upgrade(move |socket| handle_connection(socket, registry))
+        })
+}
+
+async fn handle_connection(socket: WebSocket, registry: Registry) {
+    let (mut client_tx, mut client_rx) = socket.split();
+
+    let subscribe = match client_rx.next().await {
+        Some(Ok(msg)) if msg.is_text() => serde_json::from_str::<Subscribe>(msg.to_str().unwrap_or("")),
+        _ => return,
+    };
+    let subscribe = match subscribe {
+        Ok(s) => s,
+        Err(_) => {
+            let _ = client_tx
+                .send(Message::text(r#"{"error":"invalid subscription"}"#))
+                .await;
+            return;
+        }
+    };
+
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    registry
+        .add(Subscription {
+            bbox: subscribe.bbox,
+            min_crowded_level: subscribe.min_crowded_level,
+            tx,
+        })
+        .await;
+
+    // Forward broadcasted events until the client disconnects; we don't
+    // expect further inbound messages on this connection.
+    while let Some(message) = rx.recv().await {
+        if client_tx.send(message).await.is_err() {
+            break;
+        }
+    }
+}